@@ -0,0 +1,85 @@
+//! credential and api url
+//!
+//! environment variables take precedence over the json config file.
+use jqdata::{Error, Result};
+use serde_derive::Deserialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct Config {
+    pub mob: Option<String>,
+    pub pwd: Option<String>,
+    pub url: Option<String>,
+}
+
+impl Config {
+    /// load config file and apply environment variables,
+    /// an explicitly given file must exist
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        let mut config = match path {
+            Some(path) => Config::read(path)?,
+            None => match default_path() {
+                Some(path) if path.exists() => Config::read(&path)?,
+                _ => Config::default(),
+            },
+        };
+        config.apply_env(|name| std::env::var(name).ok());
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> Result<Config> {
+        let content = std::fs::read(path)
+            .map_err(|e| Error::Client(format!("failed to read config {}: {}", path.display(), e)))?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    fn apply_env<F: Fn(&str) -> Option<String>>(&mut self, var: F) {
+        if let Some(mob) = var("JQDATA_MOB") {
+            self.mob = Some(mob);
+        }
+        if let Some(pwd) = var("JQDATA_PWD") {
+            self.pwd = Some(pwd);
+        }
+        if let Some(url) = var("JQDATA_URL") {
+            self.url = Some(url);
+        }
+    }
+
+    /// mob and pwd, both are required
+    pub fn credential(&self) -> Result<(String, String)> {
+        match (&self.mob, &self.pwd) {
+            (Some(mob), Some(pwd)) => Ok((mob.to_owned(), pwd.to_owned())),
+            _ => Err(Error::Client(
+                "credential not found, set JQDATA_MOB and JQDATA_PWD or use a config file".to_owned(),
+            )),
+        }
+    }
+}
+
+/// JQDATA_CONFIG, or ~/.config/jqdata/config.json
+fn default_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("JQDATA_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/jqdata/config.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_overrides_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        std::fs::write(&path, r#"{"mob": "10000", "pwd": "file"}"#).unwrap();
+        let mut config = Config::read(&path).unwrap();
+        config.apply_env(|name| match name {
+            "JQDATA_PWD" => Some("env".to_owned()),
+            _ => None,
+        });
+        assert_eq!(("10000".to_owned(), "env".to_owned()), config.credential().unwrap());
+        assert!(config.url.is_none());
+        assert!(Config::default().credential().is_err());
+    }
+}
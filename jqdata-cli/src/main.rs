@@ -0,0 +1,323 @@
+//! JQData command-line tool
+//!
+//! Queries JQData with subcommands mirroring the request models
+//! and writes the results as table, csv, json lines or parquet.
+mod config;
+mod output;
+
+use crate::config::Config;
+use crate::output::{column_table, rows_to_table, write_table, write_table_file, Format};
+use clap::{Parser, Subcommand};
+use jqdata::*;
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(name = "jqdata", version, about = "Query and export JQData")]
+struct Cli {
+    /// output format
+    #[arg(long, short, value_enum, default_value_t = Format::Table, global = true)]
+    format: Format,
+
+    /// output file, stdout if not set
+    #[arg(long, short, global = true)]
+    output: Option<PathBuf>,
+
+    /// json config file with mob, pwd and url
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// all securities of given kind
+    Securities {
+        /// stock, fund, index, futures, etf, lof, options, ...
+        #[arg(long, default_value = "stock")]
+        kind: String,
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// information of one security
+    Security { code: String },
+    /// trade days between two dates
+    TradeDays {
+        #[arg(long)]
+        from: String,
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// bars of one security, by date range or by count
+    Price {
+        code: String,
+        /// 1m, 5m, 15m, 30m, 60m, 120m, 1d, 1w or 1M
+        #[arg(long, default_value = "1d")]
+        unit: String,
+        #[arg(long, conflicts_with = "count", requires = "to")]
+        from: Option<String>,
+        #[arg(long)]
+        to: Option<String>,
+        /// number of bars until --to or now
+        #[arg(long)]
+        count: Option<u32>,
+        /// reference date of price adjustment, unadjusted if not set
+        #[arg(long)]
+        fq_ref_date: Option<String>,
+    },
+    /// ticks of one security between two times
+    Ticks {
+        code: String,
+        #[arg(long)]
+        from: String,
+        #[arg(long)]
+        to: String,
+        /// skip ticks without trades
+        #[arg(long)]
+        skip: bool,
+    },
+    /// run_query on finance, macro and options tables
+    Query {
+        /// database and table, such as finance.STK_XR_XD
+        table: String,
+        /// comma separated columns, all if not set
+        #[arg(long, default_value = "")]
+        columns: String,
+        /// conditions such as report_date#>=#2006-12-01
+        #[arg(long)]
+        conditions: Option<String>,
+        /// number of rows, at most 1000
+        #[arg(long)]
+        count: Option<u32>,
+    },
+    /// remaining query count of today
+    Quota,
+    /// download datasets of many securities into a directory,
+    /// running again resumes the pending requests
+    Bulk {
+        /// job directory with manifest.json
+        dir: PathBuf,
+        /// constituents of index
+        #[arg(long, group = "universe")]
+        index: Option<String>,
+        /// stocks of industry
+        #[arg(long, group = "universe")]
+        industry: Option<String>,
+        /// comma separated security codes
+        #[arg(long, group = "universe", value_delimiter = ',')]
+        codes: Vec<String>,
+        #[arg(long, required_unless_present = "resume")]
+        from: Option<String>,
+        #[arg(long, required_unless_present = "resume")]
+        to: Option<String>,
+        /// comma separated datasets: price, money-flow, mtss
+        #[arg(long, value_enum, value_delimiter = ',', default_value = "price")]
+        dataset: Vec<BulkDataset>,
+        #[arg(long, default_value = "1d")]
+        unit: String,
+        #[arg(long, default_value_t = 4)]
+        concurrency: usize,
+        /// stop when remaining query count is below this value
+        #[arg(long)]
+        quota_reserve: Option<i32>,
+        /// resume the saved job without planning
+        #[arg(long)]
+        resume: bool,
+    },
+    /// any method with key=value params, csv response is expected
+    Raw {
+        method: String,
+        params: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum BulkDataset {
+    Price,
+    MoneyFlow,
+    Mtss,
+}
+
+impl From<BulkDataset> for Dataset {
+    fn from(dataset: BulkDataset) -> Self {
+        match dataset {
+            BulkDataset::Price => Dataset::Price,
+            BulkDataset::MoneyFlow => Dataset::MoneyFlow,
+            BulkDataset::Mtss => Dataset::Mtss,
+        }
+    }
+}
+
+fn parse_kind(kind: &str) -> Result<SecurityKind> {
+    serde_json::from_value(Value::String(kind.to_owned()))
+        .map_err(|_| Error::Client(format!("unknown security kind {}", kind)))
+}
+
+/// parse key=value pairs, values that are json numbers or
+/// booleans are sent as such
+fn parse_params(params: &[String]) -> Result<Value> {
+    let mut map = Map::new();
+    for param in params {
+        let (key, value) = param
+            .split_once('=')
+            .ok_or_else(|| Error::Client(format!("param {} is not in key=value form", param)))?;
+        let value = match serde_json::from_str::<Value>(value) {
+            Ok(v @ Value::Number(_)) | Ok(v @ Value::Bool(_)) => v,
+            _ => Value::String(value.to_owned()),
+        };
+        map.insert(key.to_owned(), value);
+    }
+    Ok(Value::Object(map))
+}
+
+async fn run_command(client: &JqdataClient, command: Command) -> Result<Table> {
+    let table = match command {
+        Command::Securities { kind, date } => {
+            let code = parse_kind(&kind)?;
+            rows_to_table(&client.execute(GetAllSecurities { code, date }).await?)?
+        }
+        Command::Security { code } => rows_to_table(&client.execute(GetSecurityInfo { code }).await?)?,
+        Command::TradeDays { from, to } => column_table(
+            "date",
+            client.execute(GetTradeDays { date: from, end_date: to }).await?,
+        ),
+        Command::Price { code, unit, from, to, count, fq_ref_date } => {
+            let rows = match (from, count) {
+                (Some(date), _) => {
+                    let end_date = to.unwrap_or_default();
+                    client
+                        .execute(GetPricePeriod { code, unit, date, end_date, fq_ref_date })
+                        .await?
+                }
+                (None, Some(count)) => {
+                    client
+                        .execute(GetPrice { code, count, unit, end_date: to, fq_ref_date })
+                        .await?
+                }
+                (None, None) => return Err(Error::Client("either --from or --count is required".to_owned())),
+            };
+            rows_to_table(&rows)?
+        }
+        Command::Ticks { code, from, to, skip } => rows_to_table(
+            &client
+                .execute(GetTicksPeriod { code, date: from, end_date: to, skip })
+                .await?,
+        )?,
+        Command::Query { table, columns, conditions, count } => {
+            let lines = client
+                .execute(RunQuery { table, columns, conditions, count })
+                .await?;
+            Table::from_lines(&lines)?
+        }
+        Command::Quota => column_table("count", vec![client.execute(GetQueryCount {}).await?.to_string()]),
+        Command::Raw { method, params } => client.execute_raw(&method, parse_params(&params)?).await?,
+        Command::Bulk {
+            dir,
+            index,
+            industry,
+            codes,
+            from,
+            to,
+            dataset,
+            unit,
+            concurrency,
+            quota_reserve,
+            resume,
+        } => {
+            let mut bulk = BulkDownload::new(client.clone(), dir)?.concurrency(concurrency);
+            if let Some(reserve) = quota_reserve {
+                bulk = bulk.quota_reserve(reserve);
+            }
+            let report = if resume {
+                bulk.resume().await?
+            } else {
+                let codes = match (index, industry) {
+                    (Some(index), _) => CodeSet::Index(index),
+                    (None, Some(industry)) => CodeSet::Industry(industry),
+                    (None, None) if !codes.is_empty() => CodeSet::Codes(codes),
+                    _ => return Err(Error::Client("one of --index, --industry or --codes is required".to_owned())),
+                };
+                let spec = BulkSpec {
+                    codes,
+                    datasets: dataset.into_iter().map(Dataset::from).collect(),
+                    unit,
+                    start_date: from.unwrap_or_default(),
+                    end_date: to.unwrap_or_default(),
+                };
+                bulk.download(spec).await?
+            };
+            Table {
+                columns: ["completed", "pending", "rows", "quota_exhausted"].iter().map(|c| (*c).to_owned()).collect(),
+                rows: vec![vec![
+                    report.completed.to_string(),
+                    report.pending.to_string(),
+                    report.rows.to_string(),
+                    report.quota_exhausted.to_string(),
+                ]],
+            }
+        }
+    };
+    Ok(table)
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    if cli.format == Format::Parquet && cli.output.is_none() {
+        return Err(Error::Client("parquet output requires --output".to_owned()));
+    }
+    let config = Config::load(cli.config.as_deref())?;
+    let (mob, pwd) = config.credential()?;
+    let transport = match config.url {
+        Some(ref url) => ReqwestTransport::with_url(url.as_str()),
+        None => ReqwestTransport::new(),
+    };
+    let client = JqdataClient::with_transport(transport, mob, pwd).await?;
+    let table = run_command(&client, cli.command).await?;
+    match cli.output {
+        Some(path) => write_table_file(&table, cli.format, &path),
+        None => write_table(&table, cli.format, &mut std::io::stdout().lock()),
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli).await {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use serde_json::json;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+        let cli = Cli::parse_from(["jqdata", "price", "000001.XSHE", "--from", "2020-01-02", "--to", "2020-01-10", "-f", "csv"]);
+        assert_eq!(Format::Csv, cli.format);
+        assert!(matches!(cli.command, Command::Price { count: None, .. }));
+        assert!(Cli::try_parse_from(["jqdata", "price", "000001.XSHE", "--from", "2020-01-02"]).is_err());
+        let cli = Cli::parse_from(["jqdata", "bulk", "data", "--codes", "000001.XSHE,600000.XSHG", "--from", "2020-01-01", "--to", "2020-12-31", "--dataset", "price,mtss"]);
+        match cli.command {
+            Command::Bulk { codes, dataset, .. } => {
+                assert_eq!(2, codes.len());
+                assert_eq!(vec![BulkDataset::Price, BulkDataset::Mtss], dataset);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+        assert!(Cli::try_parse_from(["jqdata", "bulk", "data", "--index", "000300.XSHG", "--codes", "000001.XSHE"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_params() {
+        let params = parse_params(&["code=000001.XSHE".to_owned(), "count=10".to_owned(), "skip=true".to_owned()]).unwrap();
+        assert_eq!(json!({"code": "000001.XSHE", "count": 10, "skip": true}), params);
+        assert!(parse_params(&["code".to_owned()]).is_err());
+        assert_eq!(SecurityKind::ETF, parse_kind("etf").unwrap());
+    }
+}
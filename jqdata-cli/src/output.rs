@@ -0,0 +1,173 @@
+//! output of tables in different formats
+use clap::ValueEnum;
+use jqdata::{Error, Result, Table};
+use serde::Serialize;
+use serde_json::Value;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// aligned columns for terminal
+    Table,
+    Csv,
+    /// one json object per line
+    Jsonl,
+    /// parquet file, requires --output
+    Parquet,
+}
+
+/// convert typed rows into table
+pub fn rows_to_table<T: Serialize>(rows: &[T]) -> Result<Table> {
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    let body = writer.into_inner().map_err(|e| Error::Client(e.to_string()))?;
+    if body.is_empty() {
+        return Ok(Table::default());
+    }
+    Table::from_csv(body.as_slice())
+}
+
+/// single column table
+pub fn column_table(name: &str, values: Vec<String>) -> Table {
+    Table {
+        columns: vec![name.to_owned()],
+        rows: values.into_iter().map(|v| vec![v]).collect(),
+    }
+}
+
+pub fn write_table<W: Write>(table: &Table, format: Format, out: &mut W) -> Result<()> {
+    match format {
+        Format::Table => write_aligned(table, out),
+        Format::Csv => write_csv(table, out),
+        Format::Jsonl => write_jsonl(table, out),
+        Format::Parquet => Err(Error::Client("parquet output requires --output".to_owned())),
+    }
+}
+
+/// write table to file, parquet is written as binary
+pub fn write_table_file(table: &Table, format: Format, path: &Path) -> Result<()> {
+    if format == Format::Parquet {
+        return write_parquet(table, path);
+    }
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    write_table(table, format, &mut file)?;
+    file.flush()?;
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(table: &Table, path: &Path) -> Result<()> {
+    let mut body = Vec::new();
+    write_csv(table, &mut body)?;
+    let batch = jqdata::csv_to_record_batch(&body)?;
+    jqdata::write_parquet_file(&batch, path)
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_parquet(_table: &Table, _path: &Path) -> Result<()> {
+    Err(Error::Client("parquet output requires feature parquet".to_owned()))
+}
+
+fn write_csv<W: Write>(table: &Table, out: &mut W) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(&table.columns)?;
+    for row in &table.rows {
+        writer.write_record(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// keys are written in column order, which a json map
+/// would not keep
+fn write_jsonl<W: Write>(table: &Table, out: &mut W) -> Result<()> {
+    for row in &table.rows {
+        let fields: Vec<String> = table
+            .columns
+            .iter()
+            .zip(row)
+            .map(|(c, v)| format!("{}:{}", Value::from(c.as_str()), Value::from(v.as_str())))
+            .collect();
+        writeln!(out, "{{{}}}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+/// east asian wide characters take two columns in terminal
+fn is_wide(c: char) -> bool {
+    matches!(c as u32,
+        0x1100..=0x115F
+        | 0x2E80..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6)
+}
+
+fn display_width(s: &str) -> usize {
+    s.chars().map(|c| if is_wide(c) { 2 } else { 1 }).sum()
+}
+
+fn write_aligned<W: Write>(table: &Table, out: &mut W) -> Result<()> {
+    let mut widths: Vec<usize> = table.columns.iter().map(|c| display_width(c)).collect();
+    for row in &table.rows {
+        for (w, v) in widths.iter_mut().zip(row) {
+            *w = (*w).max(display_width(v));
+        }
+    }
+    let line = |values: &[String]| {
+        let cells: Vec<String> = values
+            .iter()
+            .zip(&widths)
+            .map(|(v, w)| format!("{}{}", v, " ".repeat(w - display_width(v))))
+            .collect();
+        cells.join("  ").trim_end().to_owned()
+    };
+    writeln!(out, "{}", line(&table.columns))?;
+    for row in &table.rows {
+        writeln!(out, "{}", line(row))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Table {
+        Table {
+            columns: vec!["code".to_owned(), "display_name".to_owned()],
+            rows: vec![
+                vec!["000001.XSHE".to_owned(), "平安银行".to_owned()],
+                vec!["000002.XSHE".to_owned(), "万科A".to_owned()],
+            ],
+        }
+    }
+
+    fn render(format: Format) -> String {
+        let mut out = Vec::new();
+        write_table(&table(), format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_write_aligned() {
+        assert_eq!(
+            "code         display_name\n000001.XSHE  平安银行\n000002.XSHE  万科A\n",
+            render(Format::Table)
+        );
+    }
+
+    #[test]
+    fn test_write_csv_and_jsonl() {
+        assert_eq!("code,display_name\n000001.XSHE,平安银行\n000002.XSHE,万科A\n", render(Format::Csv));
+        let first = render(Format::Jsonl).lines().next().unwrap().to_owned();
+        assert_eq!(r#"{"code":"000001.XSHE","display_name":"平安银行"}"#, first);
+        let value: Value = serde_json::from_str(&first).unwrap();
+        assert_eq!("平安银行", value["display_name"]);
+    }
+}
@@ -1,15 +1,18 @@
 //! Defines derive macro to generate implementions of 
 //! each request type defines in jqdata-model crate.
+//!
+//! Generated code refers to `::jqdata_model` by default, crates
+//! using the macro through a re-export can override the path with
+//! `#[jqdata(crate = "jqdata")]`.
 
 extern crate proc_macro;
 use proc_macro::TokenStream;
-use proc_macro2;
 use quote::*;
 use syn::{parse_macro_input, DeriveInput};
 
 /// entrypoint of derive macro to implements HasMethod and BodyConsumer traits on
 /// marked structs
-#[proc_macro_derive(Jqdata, attributes(method, consume))]
+#[proc_macro_derive(Jqdata, attributes(method, consume, jqdata))]
 pub fn derive_jqdata(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
     let result = match ast.data {
@@ -24,14 +27,41 @@ fn derive_jqdata_for_struct(
     fields: &syn::Fields,
 ) -> proc_macro2::TokenStream {
     match *fields {
-        syn::Fields::Named(..) => impl_jqdata_for_struct(&ast),
-        syn::Fields::Unit => impl_jqdata_for_struct(&ast),
+        syn::Fields::Named(..) => impl_jqdata_for_struct(ast),
+        syn::Fields::Unit => impl_jqdata_for_struct(ast),
         syn::Fields::Unnamed(..) => panic!("doesn't work with unnamed fields yet"),
     }
 }
 
+/// path of jqdata-model crate, set by `#[jqdata(crate = "...")]`
+fn crate_path(ast: &syn::DeriveInput) -> syn::Path {
+    let path = ast
+        .attrs
+        .iter()
+        .find_map(|attr| {
+            if let Ok(syn::Meta::List(metalist)) = attr.parse_meta() {
+                if metalist.path.is_ident("jqdata") {
+                    return metalist.nested.iter().find_map(|m| {
+                        if let syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) = m {
+                            if nv.path.is_ident("crate") {
+                                if let syn::Lit::Str(ref strlit) = nv.lit {
+                                    return Some(strlit.value());
+                                }
+                            }
+                        }
+                        None
+                    });
+                }
+            }
+            None
+        })
+        .unwrap_or_else(|| "::jqdata_model".to_owned());
+    syn::parse_str(&path).expect("invalid crate path in jqdata attribute")
+}
+
 fn impl_jqdata_for_struct(ast: &syn::DeriveInput) -> proc_macro2::TokenStream {
     let struct_name = &ast.ident;
+    let krate = crate_path(ast);
 
     let request_method = ast
         .attrs
@@ -92,7 +122,7 @@ fn impl_jqdata_for_struct(ast: &syn::DeriveInput) -> proc_macro2::TokenStream {
 
     let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
 
-    let (consume_impl, output_ty) = match consume_format.as_ref() {
+    let (consume_impl, output_ty, consumer_trait, policy_impl) = match consume_format.as_ref() {
         "csv" => {
             let ty = ty.expect("type must be set in response attribute when format is csv");
             let single_ty: syn::Type = syn::parse_str(&ty.to_string())
@@ -100,11 +130,18 @@ fn impl_jqdata_for_struct(ast: &syn::DeriveInput) -> proc_macro2::TokenStream {
             let output_ty: syn::Type = syn::parse_str(&format!("Vec<{}>", ty))
                 .expect("invalid type in response attribute");
             let consume_impl = quote! {
-                impl #impl_generics crate::models::CsvListBodyConsumer for #struct_name #ty_generics #where_clause {
+                impl #impl_generics #krate::CsvListBodyConsumer for #struct_name #ty_generics #where_clause {
+                    const METHOD: &'static str = #request_method;
+
                     type Output = #single_ty;
-                } 
+                }
+            };
+            let policy_impl = quote! {
+                fn consume_body_with_policy<R: std::io::Read>(body: R, policy: #krate::SchemaPolicy) -> #krate::Result<#output_ty> {
+                    <Self as #krate::CsvListBodyConsumer>::consume_with_policy(body, policy)
+                }
             };
-            (consume_impl, output_ty)
+            (consume_impl, output_ty, quote! { #krate::CsvListBodyConsumer }, policy_impl)
         }
         "line" => {
             if ty.is_some() {
@@ -112,42 +149,45 @@ fn impl_jqdata_for_struct(ast: &syn::DeriveInput) -> proc_macro2::TokenStream {
             }
             let output_ty: syn::Type = syn::parse_str("Vec<String>").unwrap();
             let consume_impl = quote! {
-                impl #impl_generics crate::models::LineBodyConsumer for #struct_name #ty_generics #where_clause {}
+                impl #impl_generics #krate::LineBodyConsumer for #struct_name #ty_generics #where_clause {}
             };
-            (consume_impl, output_ty)
+            (consume_impl, output_ty, quote! { #krate::LineBodyConsumer }, quote! {})
         }
         "single" => {
             let output_ty = ty.expect("type must be set in response attribute when format is single");
             let output_ty: syn::Type = syn::parse_str(&output_ty).expect("invalid type in response attribute");
             let consume_impl = quote! {
-                impl #impl_generics crate::models::SingleBodyConsumer<#output_ty> for #struct_name #ty_generics #where_clause {}
+                impl #impl_generics #krate::SingleBodyConsumer<#output_ty> for #struct_name #ty_generics #where_clause {}
             };
-            (consume_impl, output_ty)
+            let consumer_trait = quote! { #krate::SingleBodyConsumer<#output_ty> };
+            (consume_impl, output_ty, consumer_trait, quote! {})
         }
         "json" => {
             let output_ty = ty.expect("type must be set in response attribute when format is json");
             let output_ty: syn::Type = syn::parse_str(&output_ty).expect("invalid type in response attribute");
             let consume_impl = quote! {
-                impl #impl_generics crate::models::JsonBodyConsumer for #struct_name #ty_generics #where_clause {
+                impl #impl_generics #krate::JsonBodyConsumer for #struct_name #ty_generics #where_clause {
                     type Output = #output_ty;
                 }
             };
-            (consume_impl, output_ty)
+            (consume_impl, output_ty, quote! { #krate::JsonBodyConsumer }, quote! {})
         },
         _ => panic!("format {} not supported", consume_format),
     };
 
     quote! {
-        impl #impl_generics crate::models::HasMethod for #struct_name #ty_generics #where_clause {
+        impl #impl_generics #krate::HasMethod for #struct_name #ty_generics #where_clause {
             fn method(&self) -> String {
                 #request_method.to_owned()
             }
         }
 
-        impl #impl_generics crate::models::BodyConsumer<#output_ty> for #struct_name #ty_generics #where_clause {
-            fn consume_body<R: std::io::Read>(body: R) -> crate::Result<#output_ty> {
-                Self::consume(body)
+        impl #impl_generics #krate::BodyConsumer<#output_ty> for #struct_name #ty_generics #where_clause {
+            fn consume_body<R: std::io::Read>(body: R) -> #krate::Result<#output_ty> {
+                <Self as #consumer_trait>::consume(body)
             }
+
+            #policy_impl
         }
 
         #consume_impl
@@ -0,0 +1,125 @@
+//! request handling, independent of the HTTP server
+use crate::synthetic;
+use crate::validate::validate;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+/// Handler
+///
+/// answers request bodies the same way as JQData API: all
+/// responses are plain text, failures are reported as
+/// bodies starting with "error: "
+#[derive(Debug, Clone)]
+pub struct Handler {
+    pub token: String,
+    pub fixtures: Option<PathBuf>,
+    pub synthetic: bool,
+}
+
+fn error<S: AsRef<str>>(msg: S) -> String {
+    format!("error: {}", msg.as_ref())
+}
+
+impl Handler {
+    pub fn handle(&self, body: &str) -> String {
+        let mut payload: Map<String, Value> = match serde_json::from_str(body) {
+            Ok(Value::Object(map)) => map,
+            _ => return error("request body must be a json object"),
+        };
+        let method = match payload.remove("method") {
+            Some(Value::String(method)) => method,
+            _ => return error("method not specified"),
+        };
+        if method == "get_token" || method == "get_current_token" {
+            return self.token(&payload);
+        }
+        match payload.remove("token") {
+            Some(Value::String(ref token)) if *token == self.token => (),
+            _ => return error("token is invalid, please get a new token"),
+        }
+        let payload = Value::Object(payload);
+        if let Err(e) = validate(&method, payload.clone()) {
+            return error(format!("invalid parameters for {}: {}", method, e));
+        }
+        if let Some(body) = self.fixture(&method, &payload) {
+            return body;
+        }
+        if self.synthetic {
+            if let Some(result) = synthetic::respond(&method, &payload) {
+                return result.unwrap_or_else(error);
+            }
+        }
+        error(format!("no data available for method {}", method))
+    }
+
+    fn token(&self, payload: &Map<String, Value>) -> String {
+        let has_credential = ["mob", "pwd"]
+            .iter()
+            .all(|k| payload.get(*k).and_then(Value::as_str).map(|s| !s.is_empty()).unwrap_or(false));
+        if !has_credential {
+            return error("mob and pwd are required");
+        }
+        self.token.clone()
+    }
+
+    /// lookup <dir>/<method>/<code>.* and then <dir>/<method>.*
+    fn fixture(&self, method: &str, payload: &Value) -> Option<String> {
+        let dir = self.fixtures.as_ref()?;
+        let by_code = payload
+            .get("code")
+            .and_then(Value::as_str)
+            .and_then(|code| find_file(&dir.join(method), code));
+        by_code
+            .or_else(|| find_file(dir, method))
+            .and_then(|path| std::fs::read_to_string(path).ok())
+    }
+}
+
+fn find_file(dir: &Path, stem: &str) -> Option<PathBuf> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .find(|path| path.file_stem().and_then(|s| s.to_str()) == Some(stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn handler(fixtures: Option<PathBuf>) -> Handler {
+        Handler {
+            token: "mock".to_owned(),
+            fixtures,
+            synthetic: true,
+        }
+    }
+
+    #[test]
+    fn test_token_and_validation() {
+        let h = handler(None);
+        assert_eq!("mock", h.handle(&json!({"method": "get_current_token", "mob": "1", "pwd": "2"}).to_string()));
+        assert!(h.handle(&json!({"method": "get_current_token"}).to_string()).starts_with("error"));
+        assert!(h
+            .handle(&json!({"method": "get_query_count", "token": "other"}).to_string())
+            .starts_with("error"));
+        // missing required field "unit"
+        let invalid = json!({"method": "get_price_period", "token": "mock", "code": "000001.XSHE", "date": "2020-01-01", "end_date": "2020-01-10"});
+        assert!(h.handle(&invalid.to_string()).starts_with("error: invalid parameters for get_price_period"));
+        assert_eq!("1000000", h.handle(&json!({"method": "get_query_count", "token": "mock"}).to_string()));
+    }
+
+    #[test]
+    fn test_fixture_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("get_index_stocks")).unwrap();
+        std::fs::write(dir.path().join("get_index_stocks").join("000300.XSHG.txt"), "600000.XSHG").unwrap();
+        std::fs::write(dir.path().join("get_index_stocks.txt"), "000001.XSHE").unwrap();
+        let h = handler(Some(dir.path().to_owned()));
+        let req = |code: &str| json!({"method": "get_index_stocks", "token": "mock", "code": code, "date": "2020-01-02"}).to_string();
+        assert_eq!("600000.XSHG", h.handle(&req("000300.XSHG")));
+        assert_eq!("000001.XSHE", h.handle(&req("000905.XSHG")));
+    }
+}
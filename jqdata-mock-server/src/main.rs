@@ -0,0 +1,85 @@
+//! JQData mock server
+//!
+//! Serves the POST-JSON protocol of JQData API from fixture
+//! files or a synthetic dataset, for offline development and
+//! tests in any language.
+mod handler;
+mod synthetic;
+mod validate;
+
+use crate::handler::Handler;
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: jqdata-mock-server [--addr ADDR] [--token TOKEN] [--fixtures DIR] [--synthetic]
+
+Options:
+    --addr ADDR       listen address, default 127.0.0.1:8080
+    --token TOKEN     token returned by get_current_token, default mock-token
+    --fixtures DIR    directory of fixture files
+    --synthetic       answer with synthetic dataset if no fixture found
+    --methods         list supported methods and exit";
+
+fn parse_args() -> Result<(String, Handler), String> {
+    let mut addr = "127.0.0.1:8080".to_owned();
+    let mut handler = Handler {
+        token: "mock-token".to_owned(),
+        fixtures: None,
+        synthetic: false,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("missing value of {}", name));
+        match arg.as_str() {
+            "--addr" => addr = value("--addr")?,
+            "--token" => handler.token = value("--token")?,
+            "--fixtures" => handler.fixtures = Some(PathBuf::from(value("--fixtures")?)),
+            "--synthetic" => handler.synthetic = true,
+            "--methods" => {
+                for method in validate::METHODS {
+                    println!("{}", method);
+                }
+                std::process::exit(0);
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            other => return Err(format!("unknown argument {}", other)),
+        }
+    }
+    if handler.fixtures.is_none() && !handler.synthetic {
+        return Err("either --fixtures or --synthetic must be specified".to_owned());
+    }
+    Ok((addr, handler))
+}
+
+fn main() {
+    let (addr, handler) = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let server = match tiny_http::Server::http(&addr) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("failed to listen on {}: {}", addr, e);
+            std::process::exit(1);
+        }
+    };
+    eprintln!("jqdata mock server listening on {}", addr);
+    for mut request in server.incoming_requests() {
+        let mut body = String::new();
+        let response = if *request.method() != tiny_http::Method::Post {
+            "error: only POST is supported".to_owned()
+        } else if request.as_reader().read_to_string(&mut body).is_err() {
+            "error: request body must be utf-8".to_owned()
+        } else {
+            handler.handle(&body)
+        };
+        if let Err(e) = request.respond(tiny_http::Response::from_string(response)) {
+            eprintln!("failed to respond: {}", e);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! deterministic synthetic dataset
+//!
+//! trade days are all weekdays since 2005-01-04, prices are
+//! random walks seeded by security code, so the same request
+//! always receives the same response.
+use chrono::{Datelike, Duration, Local, NaiveDate, Weekday};
+use serde_json::Value;
+
+const FIRST_TRADE_DAY: &str = "2005-01-04";
+
+const STOCKS: &[(&str, &str, &str, &str)] = &[
+    ("000001.XSHE", "平安银行", "PAYH", "1991-04-03"),
+    ("000002.XSHE", "万科A", "WKA", "1991-01-29"),
+    ("600000.XSHG", "浦发银行", "PFYH", "1999-11-10"),
+    ("600519.XSHG", "贵州茅台", "GZMT", "2001-08-27"),
+];
+
+const INDEXES: &[(&str, &str, &str, &str)] = &[
+    ("000300.XSHG", "沪深300", "HS300", "2005-04-08"),
+    ("000905.XSHG", "中证500", "ZZ500", "2007-01-15"),
+];
+
+const SECURITY_HEADER: &str = "code,display_name,name,start_date,end_date,type";
+
+/// generate response body of given method,
+/// None if the method is not covered by synthetic dataset
+pub fn respond(method: &str, payload: &Value) -> Option<Result<String, String>> {
+    let result = match method {
+        "get_all_securities" => all_securities(payload),
+        "get_security_info" => security_info(payload),
+        "get_all_trade_days" => Ok(lines(trade_days(first_trade_day(), today()))),
+        "get_trade_days" => trade_days_between(payload),
+        "get_index_stocks" => Ok(lines(STOCKS.iter().map(|s| s.0.to_owned()).collect())),
+        "get_price_period" => price_period(payload),
+        "get_price" => price(payload),
+        "get_query_count" => Ok("1000000".to_owned()),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn str_param<'a>(payload: &'a Value, name: &str) -> Result<&'a str, String> {
+    payload
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("parameter {} is required", name))
+}
+
+fn date_param(payload: &Value, name: &str) -> Result<NaiveDate, String> {
+    let s = str_param(payload, name)?;
+    parse_date(s).ok_or_else(|| format!("invalid date {}", s))
+}
+
+/// accepts "2020-01-02" and "2020-01-02 10:00:00"
+fn parse_date(s: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(s.get(..10)?, "%Y-%m-%d").ok()
+}
+
+fn first_trade_day() -> NaiveDate {
+    parse_date(FIRST_TRADE_DAY).unwrap()
+}
+
+fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+fn lines(lines: Vec<String>) -> String {
+    lines.join("\n")
+}
+
+fn trade_days(start: NaiveDate, end: NaiveDate) -> Vec<String> {
+    let mut days = Vec::new();
+    let mut d = start.max(first_trade_day());
+    while d <= end {
+        if d.weekday() != Weekday::Sat && d.weekday() != Weekday::Sun {
+            days.push(d.format("%Y-%m-%d").to_string());
+        }
+        d += Duration::days(1);
+    }
+    days
+}
+
+fn trade_days_between(payload: &Value) -> Result<String, String> {
+    let start = date_param(payload, "date")?;
+    let end = match payload.get("end_date") {
+        Some(_) => date_param(payload, "end_date")?,
+        None => today(),
+    };
+    Ok(lines(trade_days(start, end)))
+}
+
+fn security_row(s: &(&str, &str, &str, &str), kind: &str) -> String {
+    format!("{},{},{},{},2200-01-01,{}", s.0, s.1, s.2, s.3, kind)
+}
+
+fn all_securities(payload: &Value) -> Result<String, String> {
+    let (list, kind) = match str_param(payload, "code")? {
+        "stock" => (STOCKS, "stock"),
+        "index" => (INDEXES, "index"),
+        other => return Err(format!("synthetic dataset has no securities of type {}", other)),
+    };
+    let mut body = SECURITY_HEADER.to_owned();
+    for s in list {
+        body.push('\n');
+        body.push_str(&security_row(s, kind));
+    }
+    Ok(body)
+}
+
+fn security_info(payload: &Value) -> Result<String, String> {
+    let code = str_param(payload, "code")?;
+    let row = STOCKS
+        .iter()
+        .find(|s| s.0 == code)
+        .map(|s| security_row(s, "stock"))
+        .or_else(|| INDEXES.iter().find(|s| s.0 == code).map(|s| security_row(s, "index")))
+        .ok_or_else(|| format!("security {} not found", code))?;
+    Ok(format!("{}\n{}", SECURITY_HEADER, row))
+}
+
+/// seed derived from security code with FNV-1a
+fn seed(code: &str) -> u64 {
+    code.bytes().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// xorshift random number in [0, 1)
+fn next_random(state: &mut u64) -> f64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    (*state >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// daily bars of code from first trade day until end, the walk always
+/// starts from the first trade day so bars do not depend on range
+fn daily_bars(code: &str, end: NaiveDate) -> Vec<(String, String)> {
+    let mut state = seed(code) | 1;
+    let mut close = 10.0 + next_random(&mut state) * 90.0;
+    let mut bars = Vec::new();
+    for day in trade_days(first_trade_day(), end) {
+        let pre_close = close;
+        let open = pre_close * (1.0 + (next_random(&mut state) - 0.5) * 0.02);
+        close = (pre_close * (1.0 + (next_random(&mut state) - 0.5) * 0.08)).max(1.0);
+        let high = open.max(close) * (1.0 + next_random(&mut state) * 0.01);
+        let low = open.min(close) * (1.0 - next_random(&mut state) * 0.01);
+        let volume = (next_random(&mut state) * 1.0e7).round();
+        let avg = (open + close + high + low) / 4.0;
+        let row = format!(
+            "{},{:.2},{:.2},{:.2},{:.2},{},{:.2},0,{:.2},{:.2},{:.2},{:.2}",
+            day,
+            open,
+            close,
+            high,
+            low,
+            volume,
+            volume * avg,
+            pre_close * 1.1,
+            pre_close * 0.9,
+            avg,
+            pre_close
+        );
+        bars.push((day, row));
+    }
+    bars
+}
+
+const PRICE_HEADER: &str = "date,open,close,high,low,volume,money,paused,high_limit,low_limit,avg,pre_close";
+
+fn check_unit(payload: &Value) -> Result<(), String> {
+    match str_param(payload, "unit")? {
+        "1d" => Ok(()),
+        other => Err(format!("synthetic dataset does not support unit {}", other)),
+    }
+}
+
+fn price_period(payload: &Value) -> Result<String, String> {
+    check_unit(payload)?;
+    let code = str_param(payload, "code")?;
+    let start = date_param(payload, "date")?.format("%Y-%m-%d").to_string();
+    let end = date_param(payload, "end_date")?;
+    let mut body = PRICE_HEADER.to_owned();
+    for (day, row) in daily_bars(code, end) {
+        if day >= start {
+            body.push('\n');
+            body.push_str(&row);
+        }
+    }
+    Ok(body)
+}
+
+fn price(payload: &Value) -> Result<String, String> {
+    check_unit(payload)?;
+    let code = str_param(payload, "code")?;
+    let count = payload
+        .get("count")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| "parameter count is required".to_owned())? as usize;
+    let end = match payload.get("end_date") {
+        Some(_) => date_param(payload, "end_date")?,
+        None => today(),
+    };
+    let bars = daily_bars(code, end);
+    let mut body = PRICE_HEADER.to_owned();
+    for (_, row) in &bars[bars.len().saturating_sub(count)..] {
+        body.push('\n');
+        body.push_str(row);
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_synthetic_price_period_is_stable() {
+        let payload = json!({"code": "000001.XSHE", "unit": "1d", "date": "2020-01-06", "end_date": "2020-01-10"});
+        let body = respond("get_price_period", &payload).unwrap().unwrap();
+        let rows: Vec<&str> = body.lines().collect();
+        assert_eq!(PRICE_HEADER, rows[0]);
+        assert_eq!(6, rows.len());
+        assert!(rows[1].starts_with("2020-01-06,"));
+        assert_eq!(body, respond("get_price_period", &payload).unwrap().unwrap());
+    }
+}
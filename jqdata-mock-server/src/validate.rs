@@ -0,0 +1,59 @@
+//! validate incoming payloads with request structs
+//! defined in jqdata-model
+use jqdata_model::models::*;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+fn check<P: DeserializeOwned>(payload: Value) -> Result<(), String> {
+    serde_json::from_value::<P>(payload)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+macro_rules! validators {
+    ($($method:expr => $ty:ty,)*) => {
+        /// all methods supported by the server
+        pub const METHODS: &[&str] = &[$($method,)*];
+
+        /// check payload against the request struct of given method
+        pub fn validate(method: &str, payload: Value) -> Result<(), String> {
+            match method {
+                $($method => check::<$ty>(payload),)*
+                _ => Err(format!("unsupported method {}", method)),
+            }
+        }
+    };
+}
+
+validators! {
+    "get_all_securities" => GetAllSecurities,
+    "get_security_info" => GetSecurityInfo,
+    "get_index_stocks" => GetIndexStocks,
+    "get_margincash_stocks" => GetMargincashStocks,
+    "get_locked_shares" => GetLockedShares,
+    "get_index_weights" => GetIndexWeights,
+    "get_industries" => GetIndustries,
+    "get_industry" => GetIndustry,
+    "get_industry_stocks" => GetIndustryStocks,
+    "get_concepts" => GetConcepts,
+    "get_concept_stocks" => GetConceptStocks,
+    "get_trade_days" => GetTradeDays,
+    "get_all_trade_days" => GetAllTradeDays,
+    "get_mtss" => GetMtss,
+    "get_money_flow" => GetMoneyFlow,
+    "get_billboard_list" => GetBillboardList,
+    "get_future_contracts" => GetFutureContracts,
+    "get_dominant_future" => GetDominantFuture,
+    "get_fund_info" => GetFundInfo,
+    "get_current_tick" => GetCurrentTick,
+    "get_current_ticks" => GetCurrentTicks,
+    "get_extras" => GetExtras,
+    "get_price" => GetPrice,
+    "get_price_period" => GetPricePeriod,
+    "get_fq_factor" => GetFqFactor,
+    "get_ticks" => GetTicks,
+    "get_ticks_period" => GetTicksPeriod,
+    "get_factor_values" => GetFactorValues,
+    "run_query" => RunQuery,
+    "get_query_count" => GetQueryCount,
+}
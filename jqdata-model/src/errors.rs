@@ -6,6 +6,7 @@ pub enum Error {
     Server(String),
     Client(String),
     Serde(String),
+    Schema(SchemaError),
     Csv(csv::Error),
     Json(serde_json::Error),
     Io(std::io::Error),
@@ -18,6 +19,7 @@ impl fmt::Display for Error {
             Error::Server(ref s) => write!(f, "Server error: {}", s),
             Error::Client(ref s) => write!(f, "Client error: {}", s),
             Error::Serde(ref s) => write!(f, "Serde error: {}", s),
+            Error::Schema(ref err) => write!(f, "Schema error: {}", err),
             Error::Csv(ref err) => write!(f, "Csv error: {}", err),
             Error::Json(ref err) => write!(f, "Json error: {}", err),
             Error::Io(ref err) => write!(f, "Io error: {}", err),
@@ -32,6 +34,7 @@ impl std::error::Error for Error {
             Error::Server(..) => None,
             Error::Client(..) => None,
             Error::Serde(..) => None,
+            Error::Schema(..) => None,
             Error::Csv(ref err) => Some(err),
             Error::Json(ref err) => Some(err),
             Error::Io(ref err) => Some(err),
@@ -40,6 +43,35 @@ impl std::error::Error for Error {
     }
 }
 
+/// mismatch between csv columns of the response
+/// and fields of the row type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaError {
+    pub method: String,
+    /// columns in response but not in row type
+    pub unexpected: Vec<String>,
+    /// fields of row type not in response
+    pub missing: Vec<String>,
+    /// underlying deserialization error, if any
+    pub cause: Option<String>,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "columns of {} do not match, unexpected: [{}], missing: [{}]",
+            self.method,
+            self.unexpected.join(", "),
+            self.missing.join(", ")
+        )?;
+        if let Some(ref cause) = self.cause {
+            write!(f, ", {}", cause)?;
+        }
+        Ok(())
+    }
+}
+
 impl From<csv::Error> for Error {
     fn from(err: csv::Error) -> Error {
         Error::Csv(err)
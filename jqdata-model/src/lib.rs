@@ -2,10 +2,20 @@
 //! 
 //! Rust implementation of JQData API client
 
+// lets code generated by jqdata-derive refer to this crate
+// as ::jqdata_model from inside
+extern crate self as jqdata_model;
+
 pub mod errors;
 pub mod models;
+pub mod number;
+pub mod schema;
+pub mod table;
 
 pub use crate::errors::*;
 pub use crate::models::*;
+pub use crate::number::*;
+pub use crate::schema::*;
+pub use crate::table::*;
 
 pub type Result<T> = std::result::Result<T, Error>;
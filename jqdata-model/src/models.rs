@@ -1,9 +1,10 @@
 use serde_derive::*;
 use serde::Deserialize;
 use jqdata_derive::*;
-use bigdecimal::BigDecimal;
+use std::collections::BTreeMap;
 use std::io::Read;
-use crate::{Result, Error};
+use crate::{Result, Error, Number, SchemaError};
+use crate::schema::{struct_fields, CsvRow, SchemaPolicy};
 
 /// Request
 /// 
@@ -42,35 +43,121 @@ pub trait BodyConsumer<T>
 where for<'de> T: Deserialize<'de>
 {
     fn consume_body<R: Read>(body: R) -> Result<T>;
+
+    /// consume body with given schema policy,
+    /// only csv responses are affected by the policy
+    fn consume_body_with_policy<R: Read>(body: R, _policy: SchemaPolicy) -> Result<T> {
+        Self::consume_body(body)
+    }
 }
 
 /// consume body as csv
 /// used by jqdata-derive crate
 pub trait CsvListBodyConsumer {
-    type Output: for<'de> Deserialize<'de>;
+    /// method name used in schema errors
+    const METHOD: &'static str;
+
+    type Output: CsvRow;
 
     fn consume<R: Read>(body: R) -> Result<Vec<Self::Output>> {
+        Self::consume_with_policy(body, SchemaPolicy::default())
+    }
+
+    /// columns of the header are checked against fields of the
+    /// output type, see `SchemaPolicy` for the handling of differences
+    fn consume_with_policy<R: Read>(body: R, policy: SchemaPolicy) -> Result<Vec<Self::Output>> {
         let mut reader = csv::ReaderBuilder::new()
         // .has_headers(true)
         .from_reader(body);
         // consume the first row as header
-        let header_cols: Vec<&str> = reader.headers()?.into_iter().collect();
-        if header_cols.is_empty() {
+        let headers = reader.headers()?.clone();
+        if headers.is_empty() {
             return Err(Error::Server("empty response body returned".to_owned()));
         }
-        let first_col = header_cols.first().cloned().unwrap();
+        let first_col = headers.get(0).unwrap();
         if first_col.starts_with("error") {
             return Err(Error::Server(first_col.to_owned()));
         }
+        let fields = struct_fields::<Self::Output>();
+        let unexpected: Vec<usize> = (0..headers.len())
+            .filter(|idx| !fields.contains(&&headers[*idx]))
+            .collect();
+        let schema_error = |cause: Option<String>| {
+            Error::Schema(SchemaError {
+                method: Self::METHOD.to_owned(),
+                unexpected: unexpected.iter().map(|idx| headers[*idx].to_owned()).collect(),
+                missing: fields
+                    .iter()
+                    .filter(|f| !headers.iter().any(|h| h == **f))
+                    .map(|f| (*f).to_owned())
+                    .collect(),
+                cause,
+            })
+        };
+        if policy == SchemaPolicy::Strict && !unexpected.is_empty() {
+            return Err(schema_error(None));
+        }
         let mut rs = Vec::new();
-        for r in reader.deserialize() {
-            let s: Self::Output = r?;
+        for r in reader.records() {
+            let record = r?;
+            let mut s: Self::Output = record.deserialize(Some(&headers)).map_err(|e| {
+                if is_missing_field(&e) {
+                    schema_error(Some(e.to_string()))
+                } else {
+                    Error::Csv(e)
+                }
+            })?;
+            if let Some(extra) = s.extra_mut() {
+                for idx in &unexpected {
+                    extra.insert(headers[*idx].to_owned(), record[*idx].to_owned());
+                }
+            }
             rs.push(s);
         }
         Ok(rs)
     }
 }
 
+fn is_missing_field(err: &csv::Error) -> bool {
+    match err.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => match err.kind() {
+            csv::DeserializeErrorKind::Message(msg) => msg.starts_with("missing field"),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// implements CsvRow for row types with extra field
+macro_rules! csv_rows {
+    ($($row:ty),* $(,)?) => {
+        $(
+            impl CsvRow for $row {
+                fn extra_mut(&mut self) -> Option<&mut BTreeMap<String, String>> {
+                    Some(&mut self.extra)
+                }
+            }
+        )*
+    };
+}
+
+csv_rows!(
+    Security,
+    LockedShare,
+    IndexWeight,
+    Industry,
+    Concept,
+    Tick,
+    Extra,
+    Price,
+    FqFactor,
+    MoneyFlow,
+    Mtss,
+    BillboardStock,
+    IndustryIndex,
+    FactorValue,
+);
+
 /// consume body as lines
 /// used by jqdata-derive crate
 pub trait LineBodyConsumer {
@@ -110,7 +197,7 @@ pub trait JsonBodyConsumer {
 }
 
 /// 证券类型
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum SecurityKind {
     Stock,
@@ -136,7 +223,7 @@ pub enum SecurityKind {
 }
 
 /// 证券信息
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Security {
     pub code: String,
     pub display_name: String,
@@ -147,9 +234,21 @@ pub struct Security {
     pub kind: SecurityKind,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub parent: Option<String>,
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
 }
 
 /// 获取平台支持的所有股票、基金、指数、期货信息
+/// 参数：
+/// code: 证券类型
+/// date: 查询日期，默认为今天
+/// 返回：
+/// code: 标的代码
+/// display_name: 中文名称
+/// name: 缩写简称
+/// start_date: 上市日期
+/// end_date: 退市日期，如果没有退市则为2200-01-01
+/// type: 类型
 #[derive(Debug, Serialize, Deserialize, Jqdata)]
 #[method("get_all_securities")]
 #[consume(format = "csv", type = "Security")]
@@ -160,6 +259,10 @@ pub struct GetAllSecurities {
 }
 
 /// 获取股票/基金/指数的信息
+/// 参数：
+/// code: 证券代码
+/// 返回：同 get_all_securities
+/// parent: 分级基金的母基金代码
 #[derive(Debug, Serialize, Deserialize, Jqdata)]
 #[method("get_security_info")]
 #[consume(format = "csv", type = "Security")]
@@ -187,6 +290,16 @@ pub struct GetMargincashStocks {
 }
 
 /// 获取指定日期区间内的限售解禁数据
+/// 参数：
+/// code: 股票代码
+/// date: 开始日期
+/// end_date: 结束日期
+/// 返回：
+/// day: 解禁日期
+/// code: 股票代码
+/// num: 解禁股数
+/// rate1: 解禁股数/总股本
+/// rate2: 解禁股数/总流通股本
 #[derive(Debug, Serialize, Deserialize, Jqdata)]
 #[method("get_locked_shares")]
 #[consume(format = "csv", type = "LockedShare")]
@@ -196,18 +309,25 @@ pub struct GetLockedShares {
     pub end_date: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LockedShare {
     pub day: String,
     pub code: String,
-    pub num: BigDecimal,
-    pub rate1: BigDecimal,
-    pub rate2: BigDecimal,
+    pub num: Number,
+    pub rate1: Number,
+    pub rate2: Number,
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
 }
 
 /// 获取指数成份股给定日期的权重数据，每月更新一次
 /// code: 代表指数的标准形式代码， 形式：指数代码.交易所代码，例如"000001.XSHG"。
 /// date: 查询权重信息的日期，形式："%Y-%m-%d"，例如"2018-05-03"；
+/// 返回：
+/// code: 股票代码
+/// display_name: 股票名称
+/// date: 日期
+/// weight: 权重
 #[derive(Debug, Serialize, Deserialize, Jqdata)]
 #[method("get_index_weights")]
 #[consume(format = "csv", type = "IndexWeight")]
@@ -216,12 +336,14 @@ pub struct GetIndexWeights {
     pub date: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct IndexWeight {
     pub code: String,
     pub display_name: String,
     pub date: String,
-    pub weight: BigDecimal,
+    pub weight: Number,
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
 }
 
 /// 按照行业分类获取行业列表
@@ -232,6 +354,10 @@ pub struct IndexWeight {
 /// jq_l1: 聚宽一级行业
 /// jq_l2: 聚宽二级行业
 /// zjw: 证监会行业
+/// 返回：
+/// index: 行业代码
+/// name: 行业名称
+/// start_date: 开始日期
 #[derive(Debug, Serialize, Deserialize, Jqdata)]
 #[method("get_industries")]
 #[consume(format = "csv", type = "IndustryIndex")]
@@ -239,17 +365,23 @@ pub struct GetIndustries {
     pub code: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct IndustryIndex {
     pub index: String,
     pub name: String,
     pub start_date: String,
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
 }
 
 /// 查询股票所属行业
 /// 参数：
 /// code：证券代码
 /// date：查询的日期
+/// 返回：
+/// industry: 行业分类，如sw_l1
+/// industry_code: 行业代码
+/// industry_name: 行业名称
 #[derive(Debug, Serialize, Deserialize, Jqdata)]
 #[method("get_industry")]
 #[consume(format = "csv", type = "Industry")]
@@ -258,11 +390,13 @@ pub struct GetIndustry {
     pub date: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Industry {
     pub industry: String,
     pub industry_code: String,
     pub industry_name: String,
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
 }
 
 /// 获取在给定日期一个行业的所有股票
@@ -277,20 +411,23 @@ pub struct GetIndustryStocks {
     pub date: String,
 }
 
-/// 获取在给定日期一个概念板块的所有股票
-/// 参数：
+/// 获取概念板块列表
+/// 返回：
 /// code: 概念板块编码
-/// date: 查询日期,
+/// name: 概念板块名称
+/// start_date: 开始日期
 #[derive(Debug, Serialize, Deserialize, Jqdata)]
 #[method("get_concepts")]
 #[consume(format = "csv", type = "Concept")]
 pub struct GetConcepts {}
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Concept {
     pub code: String,
     pub name: String,
     pub start_date: String,
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
 }
 
 /// 获取在给定日期一个概念板块的所有股票
@@ -348,16 +485,19 @@ pub struct GetMtss {
     pub end_date: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Mtss {
     pub date: String,
     pub sec_code: String,
-    pub fin_value: BigDecimal,
-    pub fin_refund_value: BigDecimal,
-    pub sec_value: BigDecimal,
-    pub sec_sell_value: BigDecimal,
-    pub sec_refund_value: BigDecimal,
-    pub fin_sec_value: BigDecimal,
+    pub fin_value: Number,
+    pub fin_buy_value: Number,
+    pub fin_refund_value: Number,
+    pub sec_value: Number,
+    pub sec_sell_value: Number,
+    pub sec_refund_value: Number,
+    pub fin_sec_value: Number,
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
 }
 
 /// 获取一只股票在一个时间段内的资金流向数据，仅包含股票数据，不可用于获取期货数据
@@ -388,21 +528,23 @@ pub struct GetMoneyFlow {
     pub end_date: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MoneyFlow {
     pub date: String,
     pub sec_code: String,
-    pub change_pct: BigDecimal,
-    pub net_amount_main: BigDecimal,
-    pub net_pct_main: BigDecimal,
-    pub net_amount_xl: BigDecimal,
-    pub net_pct_xl: BigDecimal,
-    pub net_amount_l: BigDecimal,
-    pub net_pct_l: BigDecimal,
-    pub net_amount_m: BigDecimal,
-    pub net_pct_m: BigDecimal,
-    pub net_amount_s: BigDecimal,
-    pub net_pct_s: BigDecimal,
+    pub change_pct: Number,
+    pub net_amount_main: Number,
+    pub net_pct_main: Number,
+    pub net_amount_xl: Number,
+    pub net_pct_xl: Number,
+    pub net_amount_l: Number,
+    pub net_pct_l: Number,
+    pub net_amount_m: Number,
+    pub net_pct_m: Number,
+    pub net_amount_s: Number,
+    pub net_pct_s: Number,
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
 }
 
 /// 获取指定日期区间内的龙虎榜数据
@@ -422,6 +564,7 @@ pub struct MoneyFlow {
 /// buy_rate: 买入金额占比(买入金额/市场总成交额)
 /// sell_value: 卖出金额
 /// sell_rate: 卖出金额占比(卖出金额/市场总成交额)
+/// total_value: 买卖总额(买入金额 + 卖出金额)
 /// net_value: 净额(买入金额 - 卖出金额)
 /// amount: 市场总成交额
 #[derive(Debug, Serialize, Deserialize, Jqdata)]
@@ -433,7 +576,7 @@ pub struct GetBillboardList {
     pub end_date: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BillboardStock {
     pub code: String,
     pub day: String,
@@ -442,13 +585,15 @@ pub struct BillboardStock {
     pub abnormal_code: String,
     pub abnormal_name: String,
     pub sales_depart_name: String,
-    pub buy_value: BigDecimal,
-    pub buy_rate: BigDecimal,
-    pub sell_value: BigDecimal,
-    pub sell_rate: BigDecimal,
-    pub total_value: BigDecimal,
-    pub net_value: BigDecimal,
-    pub amount: BigDecimal,
+    pub buy_value: Number,
+    pub buy_rate: Number,
+    pub sell_value: Number,
+    pub sell_rate: Number,
+    pub total_value: Number,
+    pub net_value: Number,
+    pub amount: Number,
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
 }
 
 /// 获取某期货品种在指定日期下的可交易合约标的列表
@@ -502,7 +647,7 @@ pub struct GetFundInfo {
     pub date: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FundInfo {
     pub fund_name: String,
     pub fund_type: String,
@@ -512,12 +657,15 @@ pub struct FundInfo {
     pub fund_custodian_fee: String,
     pub fund_status: String,
     pub fund_size: String,
-    pub fund_share: BigDecimal,
+    #[serde(deserialize_with = "crate::number::deserialize_number")]
+    pub fund_share: Number,
     pub fund_asset_allocation_proportion: String,
     pub heavy_hold_stocks: Vec<String>,
-    pub heavy_hold_stocks_proportion: BigDecimal,
+    #[serde(deserialize_with = "crate::number::deserialize_number")]
+    pub heavy_hold_stocks_proportion: Number,
     pub heavy_hold_bond: Vec<String>,
-    pub heavy_hold_bond_proportion: BigDecimal,
+    #[serde(deserialize_with = "crate::number::deserialize_number")]
+    pub heavy_hold_bond_proportion: Number,
 }
 
 /// 获取最新的 tick 数据
@@ -530,11 +678,12 @@ pub struct FundInfo {
 /// low: 截至到当前时刻的日内最低价
 /// volume: 累计成交量
 /// money: 累计成交额
-/// position: 持仓量，期货使用
-/// a1_v~a5_v: 五档卖量
-/// a1_p~a5_p: 五档卖价
-/// b1_v~b5_v: 五档买量
-/// b1_p~b5_p: 五档买价
+/// position: 持仓量，期货和期权使用
+/// a1_v~a5_v: 五档卖量，期货只有一档
+/// a1_p~a5_p: 五档卖价，期货只有一档
+/// b1_v~b5_v: 五档买量，期货只有一档
+/// b1_p~b5_p: 五档买价，期货只有一档
+/// 指数没有盘口数据
 #[derive(Debug, Serialize, Deserialize, Jqdata)]
 #[method("get_current_tick")]
 #[consume(format = "csv", type = "Tick")]
@@ -542,40 +691,48 @@ pub struct GetCurrentTick {
     pub code: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// tick快照，适用于股票、指数、基金、期货和期权
+/// 期货只有一档盘口，指数没有盘口，缺失的档位为None
+/// 股票和指数没有持仓量
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Tick {
-    pub time: BigDecimal,
-    pub current: BigDecimal,
-    pub high: BigDecimal,
-    pub low: BigDecimal,
-    pub volumn: BigDecimal,
-    pub money: BigDecimal,
-    pub position: BigDecimal,
-    pub a1_v: BigDecimal,
-    pub a2_v: BigDecimal,
-    pub a3_v: BigDecimal,
-    pub a4_v: BigDecimal,
-    pub a5_v: BigDecimal,
-    pub a1_p: BigDecimal,
-    pub a2_p: BigDecimal,
-    pub a3_p: BigDecimal,
-    pub a4_p: BigDecimal,
-    pub a5_p: BigDecimal,
-    pub b1_v: BigDecimal,
-    pub b2_v: BigDecimal,
-    pub b3_v: BigDecimal,
-    pub b4_v: BigDecimal,
-    pub b5_v: BigDecimal,
-    pub b1_p: BigDecimal,
-    pub b2_p: BigDecimal,
-    pub b3_p: BigDecimal,
-    pub b4_p: BigDecimal,
-    pub b5_p: BigDecimal,
+    pub code: Option<String>,
+    pub time: String,
+    pub current: Number,
+    pub high: Number,
+    pub low: Number,
+    pub volume: Number,
+    pub money: Number,
+    pub position: Option<Number>,
+    pub a1_v: Option<Number>,
+    pub a2_v: Option<Number>,
+    pub a3_v: Option<Number>,
+    pub a4_v: Option<Number>,
+    pub a5_v: Option<Number>,
+    pub a1_p: Option<Number>,
+    pub a2_p: Option<Number>,
+    pub a3_p: Option<Number>,
+    pub a4_p: Option<Number>,
+    pub a5_p: Option<Number>,
+    pub b1_v: Option<Number>,
+    pub b2_v: Option<Number>,
+    pub b3_v: Option<Number>,
+    pub b4_v: Option<Number>,
+    pub b5_v: Option<Number>,
+    pub b1_p: Option<Number>,
+    pub b2_p: Option<Number>,
+    pub b3_p: Option<Number>,
+    pub b4_p: Option<Number>,
+    pub b5_p: Option<Number>,
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
 }
 
 /// 获取多标的最新的 tick 数据
 /// 参数：
 /// code: 标的代码， 多个标的使用,分隔。每次请求的标的必须是相同类型。标的类型包括： 股票、指数、场内基金、期货、期权
+/// 返回：同 get_current_tick
+/// code: 标的代码
 #[derive(Debug, Serialize, Deserialize, Jqdata)]
 #[method("get_current_ticks")]
 #[consume(format = "csv", type = "Tick")]
@@ -605,15 +762,17 @@ pub struct GetExtras {
     pub end_date: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Extra {
     pub date: String,
     pub is_st: Option<i8>,
-    pub acc_net_value: Option<f64>,
-    pub unit_net_value: Option<f64>,
-    pub futures_sett_price: Option<f64>,
-    pub futures_positions: Option<f64>,
-    pub adj_net_value: Option<f64>,
+    pub acc_net_value: Option<Number>,
+    pub unit_net_value: Option<Number>,
+    pub futures_sett_price: Option<Number>,
+    pub futures_positions: Option<Number>,
+    pub adj_net_value: Option<Number>,
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
 }
 
 /// 获取各种时间周期的bar数据，bar的分割方式与主流股票软件相同， 同时还支持返回当前时刻所在 bar 的数据。get_price 与 get_bars 合并为一个函数
@@ -643,7 +802,7 @@ pub struct Extra {
 #[method("get_price")]
 #[consume(format = "csv", type = "Price")]
 pub struct GetPrice {
-    pub date: String,
+    pub code: String,
     pub count: u32,
     pub unit: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -652,21 +811,23 @@ pub struct GetPrice {
     pub fq_ref_date: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Price {
     pub date: String,
-    pub open: BigDecimal,
-    pub close: BigDecimal,
-    pub high: BigDecimal,
-    pub low: BigDecimal,
-    pub volume: BigDecimal,
-    pub money: BigDecimal,
+    pub open: Number,
+    pub close: Number,
+    pub high: Number,
+    pub low: Number,
+    pub volume: Number,
+    pub money: Number,
     pub paused: Option<u8>,
-    pub high_limit: Option<f64>,
-    pub low_limit: Option<f64>,
-    pub avg: Option<f64>,
-    pub pre_close: Option<f64>,
-    pub open_interest: Option<f64>,
+    pub high_limit: Option<Number>,
+    pub low_limit: Option<Number>,
+    pub avg: Option<Number>,
+    pub pre_close: Option<Number>,
+    pub open_interest: Option<Number>,
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
 }
 
 /// 指定开始时间date和结束时间end_date时间段，获取行情数据
@@ -704,6 +865,33 @@ pub struct GetPricePeriod {
     pub fq_ref_date: Option<String>,
 }
 
+/// 获取复权因子
+/// 参数：
+/// code: 单只标的代码
+/// fq: 复权选项，pre 前复权，post 后复权
+/// date: 开始日期
+/// end_date: 结束日期
+/// 返回：
+/// date: 日期
+/// factor: 复权因子
+#[derive(Debug, Serialize, Deserialize, Jqdata)]
+#[method("get_fq_factor")]
+#[consume(format = "csv", type = "FqFactor")]
+pub struct GetFqFactor {
+    pub code: String,
+    pub fq: String,
+    pub date: String,
+    pub end_date: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FqFactor {
+    pub date: String,
+    pub factor: Number,
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
+}
+
 /// 获取tick数据
 /// 股票部分， 支持 2010-01-01 至今的tick数据，提供买五卖五数据
 /// 期货部分， 支持 2010-01-01 至今的tick数据，提供买一卖一数据。 如果要获取主力合约的tick数据，可以先使用get_dominant_future获取主力合约对应的标的
@@ -715,6 +903,7 @@ pub struct GetPricePeriod {
 /// skip: 默认为true，过滤掉无成交变化的tick数据；
 /// 当skip=false时，返回的tick数据会保留从2019年6月25日以来无成交有盘口变化的tick数据。
 /// 由于期权成交频率低，所以建议请求期权数据时skip设为false
+/// 返回：同 get_current_tick
 #[derive(Debug, Serialize, Deserialize, Jqdata)]
 #[method("get_ticks")]
 #[consume(format = "csv", type = "Tick")]
@@ -738,6 +927,7 @@ pub struct GetTicks {
 /// 当skip=false时，返回的tick数据会保留从2019年6月25日以来无成交有盘口变化的tick数据。
 /// 注：
 /// 如果时间跨度太大、数据量太多则可能导致请求超时，所有请控制好data-end_date之间的间隔！
+/// 返回：同 get_current_tick
 #[derive(Debug, Serialize, Deserialize, Jqdata)]
 #[method("get_ticks_period")]
 #[consume(format = "csv", type = "Tick")]
@@ -771,11 +961,13 @@ pub struct GetFactorValues {
     pub end_date: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FactorValue {
     pub date: String,
-    pub cfo_to_ev: Option<f64>,
-    pub net_profit_ratio: Option<f64>,
+    pub cfo_to_ev: Option<Number>,
+    pub net_profit_ratio: Option<Number>,
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
 }
 
 /// 模拟JQDataSDK的run_query方法
@@ -804,3 +996,259 @@ pub struct RunQuery {
 #[method("get_query_count")]
 #[consume(format = "single", type = "i32")]
 pub struct GetQueryCount {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::struct_fields;
+    use std::collections::HashMap;
+
+    const SOURCE: &str = include_str!("models.rs");
+
+    /// csv endpoint with columns documented after "返回："
+    struct Endpoint {
+        method: String,
+        row_type: String,
+        columns: Vec<String>,
+        alias: Option<String>,
+    }
+
+    /// expand "a1_v~a5_v" into a1_v, a2_v, ..., a5_v
+    fn expand_range(name: &str) -> Vec<String> {
+        let (first, last) = match name.split_once('~') {
+            Some(range) => range,
+            None => return vec![name.to_owned()],
+        };
+        let split = |s: &str| {
+            let start = s.find(|c: char| c.is_ascii_digit()).unwrap();
+            let end = start + s[start..].find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len() - start);
+            (s[..start].to_owned(), s[start..end].parse::<u32>().unwrap(), s[end..].to_owned())
+        };
+        let (prefix, from, suffix) = split(first);
+        let (_, to, _) = split(last);
+        (from..=to).map(|i| format!("{}{}{}", prefix, i, suffix)).collect()
+    }
+
+    /// column name at the beginning of documented line
+    fn column_of(line: &str) -> Option<Vec<String>> {
+        let end = line
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '~'))
+            .unwrap_or(line.len());
+        let name = &line[..end];
+        let rest = &line[end..];
+        let starts_lower = name.starts_with(|c: char| c.is_ascii_lowercase());
+        let separated = rest.is_empty() || rest.starts_with([':', '：', ' ']);
+        if starts_lower && separated {
+            Some(expand_range(name))
+        } else {
+            None
+        }
+    }
+
+    fn documented_endpoints() -> Vec<Endpoint> {
+        let mut endpoints = Vec::new();
+        let mut doc: Vec<&str> = Vec::new();
+        let mut in_doc = false;
+        let mut method = None;
+        let mut row_type = None;
+        for line in SOURCE.lines() {
+            if let Some(text) = line.strip_prefix("///") {
+                if !in_doc {
+                    doc.clear();
+                    method = None;
+                    row_type = None;
+                }
+                in_doc = true;
+                doc.push(text.trim());
+                continue;
+            }
+            in_doc = false;
+            if let Some(m) = line.strip_prefix("#[method(") {
+                method = Some(m.trim_end_matches(")]").trim_matches('"').to_owned());
+            } else if line.starts_with("#[consume(format = \"csv\"") {
+                row_type = line.split("type = \"").nth(1).map(|t| t.trim_end_matches("\")]").to_owned());
+            } else if line.starts_with("pub struct") {
+                if let (Some(method), Some(row_type)) = (method.take(), row_type.take()) {
+                    let mut columns = Vec::new();
+                    let mut alias = None;
+                    let mut returns = false;
+                    for text in &doc {
+                        if let Some(rest) = text.strip_prefix("返回") {
+                            returns = true;
+                            let rest = rest.trim_start_matches([':', '：']).trim();
+                            alias = rest.strip_prefix("同").map(|m| m.trim().to_owned());
+                        } else if returns && text.starts_with("注") {
+                            break;
+                        } else if returns {
+                            columns.extend(column_of(text).unwrap_or_default());
+                        }
+                    }
+                    endpoints.push(Endpoint { method, row_type, columns, alias });
+                }
+            }
+        }
+        for idx in 0..endpoints.len() {
+            if let Some(alias) = endpoints[idx].alias.clone() {
+                let parent = endpoints.iter().find(|e| e.method == alias).expect("unknown alias");
+                let mut columns = parent.columns.clone();
+                columns.append(&mut endpoints[idx].columns);
+                endpoints[idx].columns = columns;
+            }
+        }
+        endpoints
+    }
+
+    /// deserialize one sample row with given columns,
+    /// returning the fields of row type
+    fn sample<T: serde::de::DeserializeOwned>(columns: &[String]) -> std::result::Result<&'static [&'static str], String> {
+        let values: Vec<&str> = columns.iter().map(|c| if c == "type" { "stock" } else { "1" }).collect();
+        let body = format!("{}\n{}\n", columns.join(","), values.join(","));
+        let mut reader = csv::Reader::from_reader(body.as_bytes());
+        match reader.deserialize::<T>().next() {
+            Some(Ok(_)) => Ok(struct_fields::<T>()),
+            Some(Err(e)) => Err(e.to_string()),
+            None => Err("no row".to_owned()),
+        }
+    }
+
+    fn check(row_type: &str, columns: &[String]) -> std::result::Result<&'static [&'static str], String> {
+        match row_type {
+            "BillboardStock" => sample::<BillboardStock>(columns),
+            "Concept" => sample::<Concept>(columns),
+            "Extra" => sample::<Extra>(columns),
+            "FactorValue" => sample::<FactorValue>(columns),
+            "FqFactor" => sample::<FqFactor>(columns),
+            "IndexWeight" => sample::<IndexWeight>(columns),
+            "Industry" => sample::<Industry>(columns),
+            "IndustryIndex" => sample::<IndustryIndex>(columns),
+            "LockedShare" => sample::<LockedShare>(columns),
+            "MoneyFlow" => sample::<MoneyFlow>(columns),
+            "Mtss" => sample::<Mtss>(columns),
+            "Price" => sample::<Price>(columns),
+            "Security" => sample::<Security>(columns),
+            "Tick" => sample::<Tick>(columns),
+            other => Err(format!("row type {} is not covered", other)),
+        }
+    }
+
+    #[test]
+    fn test_documented_columns_conform_to_row_types() {
+        let endpoints = documented_endpoints();
+        assert!(endpoints.len() >= 17);
+        let mut failures = HashMap::new();
+        for endpoint in &endpoints {
+            assert!(!endpoint.columns.is_empty(), "{} documents no columns", endpoint.method);
+            match check(&endpoint.row_type, &endpoint.columns) {
+                Ok(fields) => {
+                    let missing: Vec<&String> = endpoint
+                        .columns
+                        .iter()
+                        .filter(|c| !fields.contains(&c.as_str()))
+                        .collect();
+                    if !missing.is_empty() {
+                        failures.insert(endpoint.method.clone(), format!("undeclared columns {:?}", missing));
+                    }
+                }
+                Err(e) => {
+                    failures.insert(endpoint.method.clone(), e);
+                }
+            }
+        }
+        assert!(failures.is_empty(), "{:?}", failures);
+    }
+
+    #[test]
+    fn test_expand_range() {
+        assert_eq!(vec!["a1_v", "a2_v", "a3_v"], expand_range("a1_v~a3_v"));
+        assert_eq!(vec!["close"], expand_range("close"));
+    }
+
+    const PRICES: &str = "date,open,close,high,low,volume,money,factor\n2020-01-02,16.65,16.87,16.95,16.55,153023187,2571196482.6,1.0\n";
+
+    #[test]
+    fn test_lenient_schema_captures_extra_columns() {
+        let rows = GetPrice::consume_with_policy(PRICES.as_bytes(), SchemaPolicy::Lenient).unwrap();
+        assert_eq!(Some(&"1.0".to_owned()), rows[0].extra.get("factor"));
+        assert!(rows[0].avg.is_none());
+    }
+
+    #[test]
+    fn test_strict_schema_rejects_unexpected_columns() {
+        match GetPrice::consume_with_policy(PRICES.as_bytes(), SchemaPolicy::Strict) {
+            Err(Error::Schema(e)) => {
+                assert_eq!("get_price", e.method);
+                assert_eq!(vec!["factor"], e.unexpected);
+                assert!(e.missing.contains(&"paused".to_owned()));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_strict_schema_accepts_missing_optional_columns() {
+        let body = "date,open,close,high,low,volume,money\n2020-01-02 09:31:00,16.65,16.87,16.95,16.55,153023187,2571196482.6\n";
+        let rows = GetPrice::consume_with_policy(body.as_bytes(), SchemaPolicy::Strict).unwrap();
+        assert!(rows[0].paused.is_none());
+    }
+
+    #[test]
+    fn test_missing_required_column() {
+        let body = "date,open,close,high,low,volumn,money\n2020-01-02,16.65,16.87,16.95,16.55,153023187,2571196482.6\n";
+        match GetPrice::consume_with_policy(body.as_bytes(), SchemaPolicy::Lenient) {
+            Err(Error::Schema(e)) => {
+                assert_eq!(vec!["volumn"], e.unexpected);
+                assert!(e.missing.contains(&"volume".to_owned()));
+                assert!(e.cause.unwrap().contains("missing field"));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_struct_field_types() {
+        use crate::schema::{struct_field_types, FieldKind};
+        let types = struct_field_types::<Price>();
+        assert_eq!(struct_fields::<Price>(), types.iter().map(|t| t.name).collect::<Vec<_>>().as_slice());
+        let of = |name: &str| types.iter().find(|t| t.name == name).map(|t| (t.kind, t.nullable)).unwrap();
+        assert_eq!((FieldKind::String, false), of("date"));
+        assert_eq!((FieldKind::Integer, true), of("paused"));
+        let float = cfg!(any(feature = "float", not(any(feature = "bigdecimal", feature = "rust_decimal"))));
+        let number = if float { FieldKind::Float } else { FieldKind::Decimal };
+        assert_eq!((number, false), of("open"));
+        assert_eq!((number, true), of("avg"));
+    }
+
+    #[test]
+    fn test_get_price_sends_code() {
+        let request = GetPrice {
+            code: "000001.XSHE".to_owned(),
+            count: 10,
+            unit: "1d".to_owned(),
+            end_date: None,
+            fq_ref_date: None,
+        };
+        let value = serde_json::to_value(&request).unwrap();
+        assert_eq!(Some("000001.XSHE"), value.get("code").and_then(|v| v.as_str()));
+        assert!(value.get("date").is_none());
+    }
+
+    #[test]
+    fn test_future_tick() {
+        let body = "time,current,high,low,volume,money,position,a1_v,a1_p,b1_v,b1_p\n\
+            20200102093000.5,3990.2,3995,3980,1200,4.7e8,62000,3,3990.4,5,3990.0\n";
+        let mut reader = csv::Reader::from_reader(body.as_bytes());
+        let tick: Tick = reader.deserialize().next().unwrap().unwrap();
+        assert!(tick.position.is_some());
+        assert!(tick.a1_p.is_some());
+        assert!(tick.a2_p.is_none());
+    }
+
+    #[test]
+    fn test_index_tick() {
+        let body = "time,current,high,low,volume,money\n20200102150000.0,4152.24,4172.66,4135.78,182300000,2.6e11\n";
+        let mut reader = csv::Reader::from_reader(body.as_bytes());
+        let tick: Tick = reader.deserialize().next().unwrap().unwrap();
+        assert!(tick.position.is_none());
+        assert!(tick.a1_v.is_none() && tick.b1_p.is_none());
+    }
+}
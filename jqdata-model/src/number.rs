@@ -0,0 +1,120 @@
+//! Number
+//!
+//! Numeric type of all price and amount fields, selected by
+//! cargo features. If several are enabled, "float" (f64) takes
+//! precedence over "rust_decimal" (rust_decimal::Decimal), which
+//! takes precedence over "bigdecimal" (bigdecimal::BigDecimal,
+//! default). f64 is used if none of them is enabled.
+use serde::de::{self, Deserializer, Visitor};
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "float")]
+pub type Number = f64;
+
+#[cfg(all(not(feature = "float"), feature = "rust_decimal"))]
+pub type Number = rust_decimal::Decimal;
+
+#[cfg(all(not(feature = "float"), not(feature = "rust_decimal"), feature = "bigdecimal"))]
+pub type Number = bigdecimal::BigDecimal;
+
+#[cfg(not(any(feature = "float", feature = "rust_decimal", feature = "bigdecimal")))]
+pub type Number = f64;
+
+/// conversion between numeric types and f64,
+/// used by computations that do not require exact decimals
+pub trait Numeric: Sized {
+    fn to_f64(&self) -> f64;
+
+    /// non-finite values are converted to zero for decimal types
+    fn from_f64(value: f64) -> Self;
+}
+
+impl Numeric for f64 {
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+#[cfg(feature = "bigdecimal")]
+impl Numeric for bigdecimal::BigDecimal {
+    fn to_f64(&self) -> f64 {
+        bigdecimal::ToPrimitive::to_f64(self).unwrap_or(f64::NAN)
+    }
+
+    fn from_f64(value: f64) -> Self {
+        // shortest representation instead of exact binary expansion
+        if value.is_finite() {
+            bigdecimal::BigDecimal::from_str(&value.to_string()).unwrap_or_default()
+        } else {
+            bigdecimal::BigDecimal::default()
+        }
+    }
+}
+
+#[cfg(feature = "rust_decimal")]
+impl Numeric for rust_decimal::Decimal {
+    fn to_f64(&self) -> f64 {
+        rust_decimal::prelude::ToPrimitive::to_f64(self).unwrap_or(f64::NAN)
+    }
+
+    fn from_f64(value: f64) -> Self {
+        rust_decimal::prelude::FromPrimitive::from_f64(value).unwrap_or_default()
+    }
+}
+
+struct NumberVisitor;
+
+impl<'de> Visitor<'de> for NumberVisitor {
+    type Value = Number;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a number or a numeric string")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<Number, E> {
+        Number::from_str(v.trim()).map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> std::result::Result<Number, E> {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> std::result::Result<Number, E> {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> std::result::Result<Number, E> {
+        self.visit_str(&v.to_string())
+    }
+}
+
+/// deserialize number from either json number or string,
+/// used by fields of json responses
+pub fn deserialize_number<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Number, D::Error> {
+    deserializer.deserialize_any(NumberVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_from_csv() {
+        #[derive(serde_derive::Deserialize)]
+        struct Row {
+            a: Number,
+            b: Option<Number>,
+        }
+        let mut reader = csv::Reader::from_reader("a,b\n16.65,\n".as_bytes());
+        let row: Row = reader.deserialize().next().unwrap().unwrap();
+        assert_eq!(Number::from_str("16.65").unwrap(), row.a);
+        assert!(row.b.is_none());
+        assert_eq!(16.65, row.a.to_f64());
+        assert_eq!(row.a, Number::from_f64(16.65));
+    }
+}
@@ -0,0 +1,275 @@
+//! Schema
+//!
+//! Introspection of row types, used to check response
+//! columns against the fields of the deserialized struct.
+//!
+//! How columns not declared by the row type are handled is
+//! decided by `SchemaPolicy`: lenient mode keeps them in the
+//! `extra` map of each row, strict mode rejects the response.
+use serde::de::{self, Deserialize, DeserializeOwned, Deserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use std::collections::BTreeMap;
+
+/// SchemaPolicy
+///
+/// policy applied when csv columns differ from the row type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchemaPolicy {
+    /// unexpected columns are errors, missing columns are errors
+    /// only if required, as Option fields cover columns returned
+    /// for some units or security types only
+    Strict,
+    /// unexpected columns are captured in `extra` of each row,
+    /// missing columns are errors only if required
+    #[default]
+    Lenient,
+}
+
+/// CsvRow
+///
+/// row type of csv responses, exposing the map that receives
+/// undeclared columns in lenient mode
+pub trait CsvRow: DeserializeOwned {
+    /// None if the row type does not capture extra columns
+    fn extra_mut(&mut self) -> Option<&mut BTreeMap<String, String>> {
+        None
+    }
+}
+
+/// deserializer capturing field names passed by
+/// derived Deserialize implementation
+struct FieldsDeserializer<'a> {
+    fields: &'a mut &'static [&'static str],
+}
+
+impl<'de, 'a> Deserializer<'de> for FieldsDeserializer<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.fields = fields;
+        Err(de::Error::custom("fields captured"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// field names of struct deserialized by serde derive,
+/// empty for non-struct types
+pub fn struct_fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldsDeserializer { fields: &mut fields });
+    fields
+}
+
+/// kind of value a field is deserialized from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Bool,
+    Integer,
+    Float,
+    /// decimal types such as `Number`, parsed from strings
+    Decimal,
+    String,
+    /// types without a csv representation
+    Other,
+}
+
+/// declared field of a row type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldType {
+    pub name: &'static str,
+    pub kind: FieldKind,
+    /// whether the field is an Option
+    pub nullable: bool,
+}
+
+/// deserializer recording the kind requested by a field,
+/// strings are numeric for fields known to reject other strings
+struct KindProbe<'a> {
+    field: &'a mut FieldType,
+    numeric: bool,
+}
+
+macro_rules! probe {
+    ($($method:ident => $kind:ident, $visit:ident($value:expr);)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                self.field.kind = FieldKind::$kind;
+                visitor.$visit($value)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> Deserializer<'de> for KindProbe<'a> {
+    type Error = de::value::Error;
+
+    /// any value as a numeric string, other types fail
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.field.kind = FieldKind::Decimal;
+        visitor.visit_str("0")
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.numeric {
+            self.field.kind = FieldKind::Decimal;
+            visitor.visit_str("0")
+        } else {
+            self.field.kind = FieldKind::String;
+            visitor.visit_str("")
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    probe! {
+        deserialize_bool => Bool, visit_bool(false);
+        deserialize_i8 => Integer, visit_u64(0);
+        deserialize_i16 => Integer, visit_u64(0);
+        deserialize_i32 => Integer, visit_u64(0);
+        deserialize_i64 => Integer, visit_u64(0);
+        deserialize_u8 => Integer, visit_u64(0);
+        deserialize_u16 => Integer, visit_u64(0);
+        deserialize_u32 => Integer, visit_u64(0);
+        deserialize_u64 => Integer, visit_u64(0);
+        deserialize_f32 => Float, visit_f64(0.0);
+        deserialize_f64 => Float, visit_f64(0.0);
+        deserialize_char => String, visit_char('0');
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.field.nullable = true;
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct enum identifier
+    }
+}
+
+/// map of field names to probed values
+struct KindsAccess<'a> {
+    fields: &'static [&'static str],
+    numeric: &'a [usize],
+    types: &'a mut Vec<FieldType>,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for KindsAccess<'a> {
+    type Error = de::value::Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        match self.fields.get(self.types.len()) {
+            Some(name) => seed.deserialize(de::value::StrDeserializer::new(name)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error> {
+        let idx = self.types.len();
+        self.types.push(FieldType {
+            name: self.fields[idx],
+            kind: FieldKind::Other,
+            nullable: false,
+        });
+        seed.deserialize(KindProbe {
+            field: self.types.last_mut().unwrap(),
+            numeric: self.numeric.contains(&idx),
+        })
+    }
+}
+
+/// deserializer passing every field of a struct to `KindProbe`
+struct KindsDeserializer<'a> {
+    numeric: &'a [usize],
+    types: &'a mut Vec<FieldType>,
+}
+
+impl<'de, 'a> Deserializer<'de> for KindsDeserializer<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(KindsAccess {
+            fields,
+            numeric: self.numeric,
+            types: self.types,
+        })
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// declared fields of a struct deriving serde Deserialize, with
+/// their kinds
+///
+/// a field read from a string is Decimal if it rejects an empty
+/// string but accepts "0". Once a field has a type the probe
+/// cannot read, that field and every field after it are reported
+/// as nullable with kind Other
+pub fn struct_field_types<'de, T: Deserialize<'de>>() -> Vec<FieldType> {
+    let fields = struct_fields::<T>();
+    let mut numeric = Vec::new();
+    let mut types = Vec::with_capacity(fields.len());
+    loop {
+        types.clear();
+        let result = T::deserialize(KindsDeserializer {
+            numeric: &numeric,
+            types: &mut types,
+        });
+        if result.is_ok() {
+            break;
+        }
+        let failed = types.len().saturating_sub(1);
+        match types.last() {
+            Some(last) if last.kind == FieldKind::String && !numeric.contains(&failed) => numeric.push(failed),
+            _ => {
+                types.truncate(failed);
+                break;
+            }
+        }
+    }
+    for name in &fields[types.len().min(fields.len())..] {
+        types.push(FieldType {
+            name,
+            kind: FieldKind::Other,
+            nullable: true,
+        });
+    }
+    types
+}
@@ -0,0 +1,146 @@
+//! Table
+//!
+//! Untyped csv response with header, for exploratory use and
+//! endpoints not yet modeled.
+use crate::{Error, Result};
+use serde::de::DeserializeOwned;
+use std::io::Read;
+use std::str::FromStr;
+
+/// Table
+///
+/// columns of header and rows of string values,
+/// empty values are kept as empty strings
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// parse csv body with header
+    pub fn from_csv<R: Read>(body: R) -> Result<Table> {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(body);
+        let columns: Vec<String> = reader.headers()?.iter().map(str::to_owned).collect();
+        match columns.first() {
+            None => return Err(Error::Server("empty response body returned".to_owned())),
+            Some(first_col) if first_col.starts_with("error") => {
+                return Err(Error::Server(first_col.to_owned()))
+            }
+            _ => (),
+        }
+        let mut rows = Vec::new();
+        for r in reader.records() {
+            rows.push(r?.iter().map(str::to_owned).collect());
+        }
+        Ok(Table { columns, rows })
+    }
+
+    /// parse lines of csv with header, such as the result of `RunQuery`
+    pub fn from_lines<S: AsRef<str>>(lines: &[S]) -> Result<Table> {
+        let body = lines.iter().map(AsRef::as_ref).collect::<Vec<_>>().join("\n");
+        Table::from_csv(body.as_bytes())
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c == name)
+    }
+
+    fn require_column(&self, name: &str) -> Result<usize> {
+        self.column_index(name)
+            .ok_or_else(|| Error::Client(format!("column {} not found", name)))
+    }
+
+    /// values of column, missing values in short rows are empty
+    pub fn column(&self, name: &str) -> Result<Vec<&str>> {
+        let idx = self.require_column(name)?;
+        Ok(self
+            .rows
+            .iter()
+            .map(|r| r.get(idx).map(String::as_str).unwrap_or_default())
+            .collect())
+    }
+
+    /// parse values of column, empty values are None
+    pub fn parse_column<T: FromStr>(&self, name: &str) -> Result<Vec<Option<T>>> {
+        self.column(name)?
+            .into_iter()
+            .map(|v| {
+                if v.is_empty() {
+                    return Ok(None);
+                }
+                v.parse()
+                    .map(Some)
+                    .map_err(|_| Error::Client(format!("invalid value {} in column {}", v, name)))
+            })
+            .collect()
+    }
+
+    /// new table with given columns in given order
+    pub fn project(&self, names: &[&str]) -> Result<Table> {
+        let indexes = names
+            .iter()
+            .map(|n| self.require_column(n))
+            .collect::<Result<Vec<_>>>()?;
+        let rows = self
+            .rows
+            .iter()
+            .map(|r| indexes.iter().map(|i| r.get(*i).cloned().unwrap_or_default()).collect())
+            .collect();
+        Ok(Table {
+            columns: names.iter().map(|n| (*n).to_owned()).collect(),
+            rows,
+        })
+    }
+
+    /// deserialize rows into typed structs by column names
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<Vec<T>> {
+        let headers = csv::StringRecord::from(self.columns.clone());
+        self.rows
+            .iter()
+            .map(|r| Ok(csv::StringRecord::from(r.clone()).deserialize(Some(&headers))?))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_derive::Deserialize;
+
+    const BODY: &str = "date,code,close,paused\n2020-01-02,000001.XSHE,16.87,0\n2020-01-03,000001.XSHE,,1\n";
+
+    #[test]
+    fn test_table_from_csv() {
+        let table = Table::from_csv(BODY.as_bytes()).unwrap();
+        assert_eq!(vec!["date", "code", "close", "paused"], table.columns);
+        assert_eq!(2, table.len());
+        assert_eq!(vec![Some(16.87), None], table.parse_column::<f64>("close").unwrap());
+        assert!(table.column("open").is_err());
+        assert!(Table::from_csv("error: invalid token".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_table_project_and_deserialize() {
+        let lines: Vec<String> = BODY.lines().map(str::to_owned).collect();
+        let table = Table::from_lines(&lines).unwrap().project(&["paused", "date"]).unwrap();
+        assert_eq!(vec!["0", "2020-01-02"], table.rows[0]);
+
+        #[derive(Deserialize)]
+        struct Row {
+            date: String,
+            paused: u8,
+        }
+        let rows: Vec<Row> = table.deserialize().unwrap();
+        assert_eq!("2020-01-03", rows[1].date);
+        assert_eq!(1, rows[1].paused);
+    }
+}
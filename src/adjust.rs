@@ -0,0 +1,255 @@
+//! Price adjustment
+//!
+//! Applies forward (前复权) and backward (后复权) adjustment to
+//! unadjusted bars locally, so stored history never needs to be
+//! downloaded again when a dividend happens.
+//!
+//! `AdjustFactors` holds the cumulative backward factor of each
+//! date, as returned by get_fq_factor with fq "post", or derived
+//! from ex-rights records of finance.STK_XR_XD. The adjusted price
+//! of date d with reference date r is `price * F(d) / F(r)`:
+//! backward adjustment uses the date whose factor is 1 as
+//! reference, forward adjustment the given date, usually the
+//! latest one. Volumes are divided by the same ratio, amounts are
+//! kept.
+//!
+//! Factors of get_fq_factor are 1 on the listing date. Factors
+//! derived from ex-rights records are 1 on the first given bar,
+//! so backward prices then depend on where the bars start.
+use crate::{GetFqFactor, JqdataClient, Numeric, Price, Result, Table};
+
+fn day_of(date: &str) -> &str {
+    date.get(..10).unwrap_or(date)
+}
+
+/// AdjustFactors
+///
+/// cumulative backward factors as a step function of date
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AdjustFactors {
+    /// (date, factor) sorted by date, only dates where
+    /// the factor changes are kept
+    points: Vec<(String, f64)>,
+}
+
+impl AdjustFactors {
+    /// factors effective from each date, in any order
+    pub fn new<S: Into<String>>(factors: Vec<(S, f64)>) -> Self {
+        let mut factors: Vec<(String, f64)> = factors.into_iter().map(|(d, f)| (d.into(), f)).collect();
+        factors.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut points: Vec<(String, f64)> = Vec::with_capacity(factors.len());
+        for (date, factor) in factors {
+            if points.last().map(|p| p.1 != factor).unwrap_or(true) {
+                points.push((date, factor));
+            }
+        }
+        AdjustFactors { points }
+    }
+
+    /// fetch backward factors of security between start and end date
+    pub async fn fetch(client: &JqdataClient, code: &str, start_date: &str, end_date: &str) -> Result<Self> {
+        let rows = client
+            .execute(GetFqFactor {
+                code: code.to_owned(),
+                fq: "post".to_owned(),
+                date: start_date.to_owned(),
+                end_date: end_date.to_owned(),
+            })
+            .await?;
+        Ok(Self::new(rows.into_iter().map(|r| (r.date, r.factor.to_f64())).collect()))
+    }
+
+    /// derive factors from ex-rights records of finance.STK_XR_XD
+    /// and unadjusted daily bars
+    ///
+    /// uses columns a_xr_date, bonus_ratio_rmb (cash per 10 shares),
+    /// dividend_ratio and transfer_ratio (shares per 10 shares).
+    /// Events without a bar before the ex-date are ignored, rights
+    /// issues are not taken into account.
+    /// The factor is 1 on the first bar, so `backward` prices are
+    /// relative to the first bar rather than the listing date
+    /// unless the bars start at listing.
+    pub fn from_xr_xd(events: &Table, bars: &[Price]) -> Result<Self> {
+        let dates = events.column("a_xr_date")?;
+        let ratio = |name: &str| -> Result<Vec<f64>> {
+            if events.column_index(name).is_none() {
+                return Ok(vec![0.0; events.len()]);
+            }
+            Ok(events.parse_column::<f64>(name)?.into_iter().map(|v| v.unwrap_or(0.0)).collect())
+        };
+        let cash = ratio("bonus_ratio_rmb")?;
+        let dividend = ratio("dividend_ratio")?;
+        let transfer = ratio("transfer_ratio")?;
+        let mut steps: Vec<(String, f64)> = Vec::new();
+        for i in 0..events.len() {
+            let ex_date = day_of(dates[i]);
+            if ex_date.is_empty() {
+                continue;
+            }
+            let prev_close = bars
+                .iter()
+                .rev()
+                .find(|b| day_of(&b.date) < ex_date)
+                .map(|b| b.close.to_f64());
+            if let Some(prev_close) = prev_close {
+                let ex_price = (prev_close - cash[i] / 10.0) / (1.0 + (dividend[i] + transfer[i]) / 10.0);
+                if ex_price > 0.0 {
+                    steps.push((ex_date.to_owned(), prev_close / ex_price));
+                }
+            }
+        }
+        steps.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut factor = 1.0;
+        let mut factors = Vec::with_capacity(steps.len() + 1);
+        if let Some(first) = bars.first() {
+            factors.push((day_of(&first.date).to_owned(), factor));
+        }
+        for (date, step) in steps {
+            factor *= step;
+            factors.push((date, factor));
+        }
+        Ok(Self::new(factors))
+    }
+
+    /// factor effective on date, 1 before the first known date
+    pub fn factor_at(&self, date: &str) -> f64 {
+        let day = day_of(date);
+        let idx = self.points.partition_point(|p| p.0.as_str() <= day);
+        idx.checked_sub(1).map(|i| self.points[i].1).unwrap_or(1.0)
+    }
+
+    fn adjust_with(&self, bars: &[Price], reference: f64) -> Vec<Price> {
+        bars.iter()
+            .map(|b| scale(b, self.factor_at(&b.date) / reference))
+            .collect()
+    }
+
+    /// forward adjustment (前复权) to prices of reference date
+    pub fn forward(&self, bars: &[Price], ref_date: &str) -> Vec<Price> {
+        self.adjust_with(bars, self.factor_at(ref_date))
+    }
+
+    /// forward adjustment to prices of the last bar
+    pub fn forward_latest(&self, bars: &[Price]) -> Vec<Price> {
+        match bars.last() {
+            Some(last) => self.forward(bars, &last.date.clone()),
+            None => Vec::new(),
+        }
+    }
+
+    /// backward adjustment (后复权) to prices of the date whose
+    /// factor is 1: the listing date for factors of get_fq_factor,
+    /// the first bar for factors derived by `from_xr_xd`
+    pub fn backward(&self, bars: &[Price]) -> Vec<Price> {
+        self.adjust_with(bars, 1.0)
+    }
+
+    /// total return index of closes with dividends reinvested,
+    /// starting from 1 at the first bar
+    pub fn total_return(&self, bars: &[Price]) -> Vec<(String, f64)> {
+        let adjusted: Vec<(String, f64)> = bars
+            .iter()
+            .map(|b| (b.date.clone(), b.close.to_f64() * self.factor_at(&b.date)))
+            .collect();
+        let base = match adjusted.first() {
+            Some((_, v)) if *v != 0.0 => *v,
+            _ => return Vec::new(),
+        };
+        adjusted.into_iter().map(|(d, v)| (d, v / base)).collect()
+    }
+}
+
+// Number is Copy only with the float feature, the default
+// bigdecimal Number needs the clones
+#[allow(clippy::clone_on_copy)]
+fn scale(bar: &Price, ratio: f64) -> Price {
+    let price = |n: &crate::Number| Numeric::from_f64(n.to_f64() * ratio);
+    let opt_price = |n: &Option<crate::Number>| n.as_ref().map(price);
+    Price {
+        date: bar.date.clone(),
+        open: price(&bar.open),
+        close: price(&bar.close),
+        high: price(&bar.high),
+        low: price(&bar.low),
+        volume: Numeric::from_f64(bar.volume.to_f64() / ratio),
+        money: bar.money.clone(),
+        paused: bar.paused,
+        high_limit: opt_price(&bar.high_limit),
+        low_limit: opt_price(&bar.low_limit),
+        avg: opt_price(&bar.avg),
+        pre_close: opt_price(&bar.pre_close),
+        open_interest: bar.open_interest.clone(),
+        extra: bar.extra.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(date: &str, close: f64) -> Price {
+        let n = |v: f64| Numeric::from_f64(v);
+        Price {
+            date: date.to_owned(),
+            open: n(close),
+            close: n(close),
+            high: n(close),
+            low: n(close),
+            volume: n(100.0),
+            money: n(close * 100.0),
+            paused: None,
+            high_limit: None,
+            low_limit: None,
+            avg: None,
+            pre_close: None,
+            open_interest: None,
+            extra: Default::default(),
+        }
+    }
+
+    fn closes(bars: &[Price]) -> Vec<f64> {
+        bars.iter().map(|b| (b.close.to_f64() * 1000.0).round() / 1000.0).collect()
+    }
+
+    #[test]
+    fn test_forward_and_backward() {
+        // 10 for 10 bonus shares on 2020-01-03 halves the price
+        let bars = vec![bar("2020-01-02", 20.0), bar("2020-01-03", 10.0), bar("2020-01-06", 11.0)];
+        let factors = AdjustFactors::new(vec![("2020-01-02", 1.0), ("2020-01-03", 2.0), ("2020-01-06", 2.0)]);
+        assert_eq!(1.0, factors.factor_at("2019-12-31"));
+        assert_eq!(2.0, factors.factor_at("2020-01-06 10:00:00"));
+        assert_eq!(vec![10.0, 10.0, 11.0], closes(&factors.forward_latest(&bars)));
+        assert_eq!(vec![20.0, 20.0, 22.0], closes(&factors.backward(&bars)));
+        assert_eq!(vec![20.0, 20.0, 22.0], closes(&factors.forward(&bars, "2020-01-02")));
+        assert_eq!(200.0, factors.forward_latest(&bars)[0].volume.to_f64());
+        let tr: Vec<f64> = factors.total_return(&bars).into_iter().map(|(_, v)| v).collect();
+        assert_eq!(vec![1.0, 1.0, 1.1], tr.iter().map(|v| (v * 1000.0).round() / 1000.0).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_backward_from_mid_history() {
+        // factors fetched from 2020-01-03 are already 2 since listing
+        let bars = vec![bar("2020-01-03", 10.0), bar("2020-01-06", 5.0)];
+        let factors = AdjustFactors::new(vec![("2020-01-03", 2.0), ("2020-01-06", 4.0)]);
+        assert_eq!(vec![20.0, 20.0], closes(&factors.backward(&bars)));
+        assert_eq!(vec![5.0, 5.0], closes(&factors.forward_latest(&bars)));
+    }
+
+    #[test]
+    fn test_factors_from_xr_xd() {
+        let bars = vec![bar("2020-01-02", 10.5), bar("2020-01-03", 10.0), bar("2020-01-06", 5.0)];
+        // cash 5 per 10 shares on 2020-01-03, 10 transfer shares per 10 on 2020-01-06
+        let events = Table {
+            columns: vec!["a_xr_date".to_owned(), "bonus_ratio_rmb".to_owned(), "transfer_ratio".to_owned()],
+            rows: vec![
+                vec!["2020-01-03".to_owned(), "5".to_owned(), "".to_owned()],
+                vec!["2020-01-06".to_owned(), "".to_owned(), "10".to_owned()],
+            ],
+        };
+        let factors = AdjustFactors::from_xr_xd(&events, &bars).unwrap();
+        assert_eq!(1.0, factors.factor_at("2020-01-02"));
+        assert!((factors.factor_at("2020-01-03") - 1.05).abs() < 1e-9);
+        assert!((factors.factor_at("2020-01-06") - 2.1).abs() < 1e-9);
+        assert_eq!(vec![5.0, 5.0, 5.0], closes(&factors.forward_latest(&bars)));
+    }
+}
@@ -0,0 +1,645 @@
+//! Bars
+//!
+//! Aggregates tick snapshots of get_ticks into OHLCV bars by time,
+//! volume or amount, and resamples minute bars into longer bars.
+//!
+//! Snapshots carry the cumulative volume and amount of the trade
+//! day, bars take the difference to the previous snapshot of the
+//! same trade day. Time bars are aligned to trading sessions as
+//! get_price minute bars are: each bar is labelled by its end time,
+//! call auction snapshots go to the first bar of a session, and no
+//! bar spans the lunch break or the gap before a night session.
+//! Intervals without any snapshot produce no bar. Resampled bars
+//! follow the same sessions.
+use crate::{Error, Market, Numeric, Price, Result, Tick, TradingCalendar};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use std::collections::HashMap;
+
+const TICK_TIME_FORMATS: &[&str] = &["%Y%m%d%H%M%S%.f", "%Y%m%d%H%M%S", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%d %H:%M:%S"];
+const BAR_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// parse time of tick, e.g. 20200102093000.5
+pub fn parse_tick_time(time: &str) -> Result<NaiveDateTime> {
+    let time = time.trim();
+    TICK_TIME_FORMATS
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(time, f).ok())
+        .ok_or_else(|| Error::Client(format!("invalid tick time {}", time)))
+}
+
+/// size of bars
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarInterval {
+    /// fixed length aligned to sessions
+    Time(Duration),
+    /// close a bar once its volume reaches the threshold
+    Volume(f64),
+    /// close a bar once its amount reaches the threshold
+    Money(f64),
+}
+
+/// cumulative values of the previous snapshot
+#[derive(Debug, Clone, Copy)]
+struct Snapshot {
+    trade_day: NaiveDate,
+    volume: f64,
+    money: f64,
+    high: f64,
+    low: f64,
+}
+
+impl Snapshot {
+    fn of(tick: &Tick, trade_day: NaiveDate) -> Self {
+        Snapshot {
+            trade_day,
+            volume: tick.volume.to_f64(),
+            money: tick.money.to_f64(),
+            high: tick.high.to_f64(),
+            low: tick.low.to_f64(),
+        }
+    }
+}
+
+/// bar in progress
+#[derive(Debug, Clone)]
+struct PartialBar {
+    /// end of time bars
+    end: Option<NaiveDateTime>,
+    last_time: NaiveDateTime,
+    open: f64,
+    close: f64,
+    high: f64,
+    low: f64,
+    volume: f64,
+    money: f64,
+    open_interest: Option<f64>,
+}
+
+impl PartialBar {
+    fn to_price(&self) -> Price {
+        let label = self.end.unwrap_or(self.last_time);
+        Price {
+            date: label.format(BAR_TIME_FORMAT).to_string(),
+            open: Numeric::from_f64(self.open),
+            close: Numeric::from_f64(self.close),
+            high: Numeric::from_f64(self.high),
+            low: Numeric::from_f64(self.low),
+            volume: Numeric::from_f64(self.volume),
+            money: Numeric::from_f64(self.money),
+            paused: None,
+            high_limit: None,
+            low_limit: None,
+            avg: None,
+            pre_close: None,
+            open_interest: self.open_interest.map(Numeric::from_f64),
+            extra: Default::default(),
+        }
+    }
+}
+
+/// TickBarBuilder
+///
+/// turns the ticks of one security, in time order, into bars
+#[derive(Debug, Clone)]
+pub struct TickBarBuilder {
+    calendar: TradingCalendar,
+    market: Market,
+    interval: BarInterval,
+    last: Option<Snapshot>,
+    bar: Option<PartialBar>,
+    /// end of the last completed time bar
+    closed: Option<NaiveDateTime>,
+}
+
+impl TickBarBuilder {
+    pub fn new(calendar: TradingCalendar, market: Market, interval: BarInterval) -> Self {
+        TickBarBuilder {
+            calendar,
+            market,
+            interval,
+            last: None,
+            bar: None,
+            closed: None,
+        }
+    }
+
+    /// add next tick, returns the bar completed by it
+    ///
+    /// ticks outside trading sessions and ticks without a price
+    /// are only used to track cumulative values. Ticks of an
+    /// already completed time bar are ignored, their volume is
+    /// counted in the next bar.
+    pub fn push(&mut self, tick: &Tick) -> Result<Option<Price>> {
+        let time = parse_tick_time(&tick.time)?;
+        let trade_day = match self.trade_day_of(time) {
+            Some(day) => day,
+            None => return Ok(None),
+        };
+        let end = match self.interval {
+            BarInterval::Time(interval) => self.calendar.bar_end(time, self.market, interval),
+            _ => None,
+        };
+        if let (Some(closed), Some(end)) = (self.closed, end) {
+            if end <= closed {
+                return Ok(None);
+            }
+        }
+        let snapshot = Snapshot::of(tick, trade_day);
+        // cumulative values restart every trade day
+        let prev = self.last.replace(snapshot).filter(|p| p.trade_day == trade_day);
+        let (volume, money) = match prev {
+            Some(p) if snapshot.volume >= p.volume => (snapshot.volume - p.volume, (snapshot.money - p.money).max(0.0)),
+            _ => (snapshot.volume, snapshot.money),
+        };
+        let current = tick.current.to_f64();
+        if current <= 0.0 {
+            return Ok(None);
+        }
+        // a new day high or low between snapshots was traded in this bar
+        let mut high = current;
+        let mut low = current;
+        if let Some(p) = prev {
+            if snapshot.high > p.high {
+                high = high.max(snapshot.high);
+            }
+            if snapshot.low < p.low && snapshot.low > 0.0 {
+                low = low.min(snapshot.low);
+            }
+        }
+        let open_interest = tick.position.as_ref().map(Numeric::to_f64);
+
+        let mut completed = None;
+        if let (Some(bar), Some(end)) = (&self.bar, end) {
+            if bar.end != Some(end) {
+                completed = self.complete();
+            }
+        }
+        match self.bar.as_mut() {
+            Some(bar) => {
+                bar.last_time = time;
+                bar.close = current;
+                bar.high = bar.high.max(high);
+                bar.low = bar.low.min(low);
+                bar.volume += volume;
+                bar.money += money;
+                if open_interest.is_some() {
+                    bar.open_interest = open_interest;
+                }
+            }
+            None => {
+                self.bar = Some(PartialBar {
+                    end,
+                    last_time: time,
+                    open: current,
+                    close: current,
+                    high,
+                    low,
+                    volume,
+                    money,
+                    open_interest,
+                })
+            }
+        }
+        let full = match (self.interval, &self.bar) {
+            (BarInterval::Volume(threshold), Some(bar)) => bar.volume >= threshold,
+            (BarInterval::Money(threshold), Some(bar)) => bar.money >= threshold,
+            _ => false,
+        };
+        if full {
+            completed = self.complete();
+        }
+        Ok(completed)
+    }
+
+    /// record cumulative values of tick without adding it to a bar
+    ///
+    /// for the first tick polled in the middle of a session, whose
+    /// volume and amount cover the trade day so far; later ticks of
+    /// the same trade day only add their difference to it
+    pub fn baseline(&mut self, tick: &Tick) -> Result<()> {
+        let time = parse_tick_time(&tick.time)?;
+        if let Some(trade_day) = self.trade_day_of(time) {
+            self.last = Some(Snapshot::of(tick, trade_day));
+        }
+        Ok(())
+    }
+
+    /// trade day of the session of time, None outside sessions
+    fn trade_day_of(&self, time: NaiveDateTime) -> Option<NaiveDate> {
+        let session = self.calendar.session_of(time, self.market)?;
+        self.calendar.trade_day_of(session.end, self.market)
+    }
+
+    /// bar in progress, if any
+    pub fn current(&self) -> Option<Price> {
+        self.bar.as_ref().map(PartialBar::to_price)
+    }
+
+    /// complete the bar in progress, e.g. at the end of data
+    pub fn finish(&mut self) -> Option<Price> {
+        self.complete()
+    }
+
+    /// complete the time bar in progress if it ended before time,
+    /// so bars close without waiting for the next tick
+    pub fn close_before(&mut self, time: NaiveDateTime) -> Option<Price> {
+        match self.bar.as_ref().and_then(|b| b.end) {
+            Some(end) if end < time => self.complete(),
+            _ => None,
+        }
+    }
+
+    fn complete(&mut self) -> Option<Price> {
+        let bar = self.bar.take()?;
+        if bar.end.is_some() {
+            self.closed = bar.end;
+        }
+        Some(bar.to_price())
+    }
+}
+
+/// aggregate ticks of one security into bars, including
+/// the last partial bar
+pub fn ticks_to_bars(
+    calendar: TradingCalendar,
+    market: Market,
+    interval: BarInterval,
+    ticks: &[Tick],
+) -> Result<Vec<Price>> {
+    let mut builder = TickBarBuilder::new(calendar, market, interval);
+    let mut bars = Vec::new();
+    for tick in ticks {
+        bars.extend(builder.push(tick)?);
+    }
+    bars.extend(builder.finish());
+    Ok(bars)
+}
+
+/// target of resampling
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResampleRule {
+    /// fixed length aligned to sessions, e.g. 3, 45 or 90 minutes
+    Time(Duration),
+    /// one bar per trading session
+    Session,
+    /// one bar per trade day, night sessions included
+    TradeDay,
+}
+
+/// Resampler
+///
+/// aggregates minute bars of get_price_period into longer bars
+///
+/// bars are labelled by their end like the input, trade day bars
+/// by the trade day. The last bar may be partial if the input ends
+/// before its end, it is kept unless partial bars are dropped.
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    calendar: TradingCalendar,
+    market: Market,
+    rule: ResampleRule,
+    drop_partial: bool,
+}
+
+impl Resampler {
+    pub fn new(calendar: TradingCalendar, market: Market, rule: ResampleRule) -> Self {
+        Resampler {
+            calendar,
+            market,
+            rule,
+            drop_partial: false,
+        }
+    }
+
+    /// drop the last bar if the input ends before its end
+    pub fn drop_partial(mut self, drop_partial: bool) -> Self {
+        self.drop_partial = drop_partial;
+        self
+    }
+
+    /// end of the target bar containing bar labelled with time,
+    /// and its label
+    fn target(&self, time: NaiveDateTime) -> Option<(NaiveDateTime, String)> {
+        match self.rule {
+            ResampleRule::Time(interval) => {
+                let end = self.calendar.bar_end(time, self.market, interval)?;
+                Some((end, end.format(BAR_TIME_FORMAT).to_string()))
+            }
+            ResampleRule::Session => {
+                let end = self.calendar.session_of(time, self.market)?.end;
+                Some((end, end.format(BAR_TIME_FORMAT).to_string()))
+            }
+            ResampleRule::TradeDay => {
+                let session = self.calendar.session_of(time, self.market)?;
+                let day = self.calendar.trade_day_of(session.end, self.market)?;
+                let end = self.calendar.sessions(day, self.market).last()?.end;
+                Some((end, day.format("%Y-%m-%d").to_string()))
+            }
+        }
+    }
+
+    /// resample bars of one security in time order, bars outside
+    /// trading sessions are skipped
+    pub fn resample(&self, bars: &[Price]) -> Result<Vec<Price>> {
+        let mut resampled = Vec::new();
+        // (end, last input time, bar)
+        let mut group: Option<(NaiveDateTime, NaiveDateTime, Price)> = None;
+        for bar in bars {
+            let time = parse_tick_time(&bar.date)?;
+            let (end, label) = match self.target(time) {
+                Some(target) => target,
+                None => continue,
+            };
+            match group.as_mut() {
+                Some((e, last, agg)) if *e == end => {
+                    *last = time;
+                    merge(agg, bar);
+                }
+                _ => {
+                    if let Some((_, _, agg)) = group.take() {
+                        resampled.push(agg);
+                    }
+                    let mut agg = bar.clone();
+                    agg.date = label;
+                    agg.avg = None;
+                    group = Some((end, time, agg));
+                }
+            }
+        }
+        if let Some((end, last, agg)) = group {
+            if !(self.drop_partial && last < end) {
+                resampled.push(agg);
+            }
+        }
+        Ok(resampled)
+    }
+}
+
+/// merge next bar into aggregated bar
+#[allow(clippy::clone_on_copy)]
+fn merge(agg: &mut Price, bar: &Price) {
+    let (high, low) = (agg.high.to_f64(), agg.low.to_f64());
+    if bar.high.to_f64() > high {
+        agg.high = bar.high.clone();
+    }
+    if bar.low.to_f64() < low {
+        agg.low = bar.low.clone();
+    }
+    agg.close = bar.close.clone();
+    agg.volume = Numeric::from_f64(agg.volume.to_f64() + bar.volume.to_f64());
+    agg.money = Numeric::from_f64(agg.money.to_f64() + bar.money.to_f64());
+    agg.paused = match (agg.paused, bar.paused) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    if bar.high_limit.is_some() {
+        agg.high_limit = bar.high_limit.clone();
+    }
+    if bar.low_limit.is_some() {
+        agg.low_limit = bar.low_limit.clone();
+    }
+    if bar.open_interest.is_some() {
+        agg.open_interest = bar.open_interest.clone();
+    }
+}
+
+/// difference between built and reference bar
+#[derive(Debug, Clone, PartialEq)]
+pub struct BarDiff {
+    pub date: String,
+    /// open, close, high, low, volume, money,
+    /// or bar if it exists on one side only
+    pub field: &'static str,
+    pub built: Option<f64>,
+    pub reference: Option<f64>,
+}
+
+/// compare bars with reference bars of the same label, e.g.
+/// from get_price, values differing more than the relative
+/// tolerance are reported
+pub fn diff_bars(built: &[Price], reference: &[Price], tolerance: f64) -> Vec<BarDiff> {
+    let fields = |p: &Price| -> [(&'static str, f64); 6] {
+        [
+            ("open", p.open.to_f64()),
+            ("close", p.close.to_f64()),
+            ("high", p.high.to_f64()),
+            ("low", p.low.to_f64()),
+            ("volume", p.volume.to_f64()),
+            ("money", p.money.to_f64()),
+        ]
+    };
+    let built_by_date: HashMap<&str, &Price> = built.iter().map(|p| (p.date.as_str(), p)).collect();
+    let reference_dates: HashMap<&str, &Price> = reference.iter().map(|p| (p.date.as_str(), p)).collect();
+    let mut diffs = Vec::new();
+    for r in reference {
+        match built_by_date.get(r.date.as_str()) {
+            Some(b) => {
+                for ((field, bv), (_, rv)) in fields(b).iter().zip(fields(r).iter()) {
+                    if (bv - rv).abs() > tolerance * rv.abs().max(1.0) {
+                        diffs.push(BarDiff {
+                            date: r.date.clone(),
+                            field,
+                            built: Some(*bv),
+                            reference: Some(*rv),
+                        });
+                    }
+                }
+            }
+            None => diffs.push(BarDiff {
+                date: r.date.clone(),
+                field: "bar",
+                built: None,
+                reference: Some(r.close.to_f64()),
+            }),
+        }
+    }
+    for b in built.iter().filter(|b| !reference_dates.contains_key(b.date.as_str())) {
+        diffs.push(BarDiff {
+            date: b.date.clone(),
+            field: "bar",
+            built: Some(b.close.to_f64()),
+            reference: None,
+        });
+    }
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calendar() -> TradingCalendar {
+        TradingCalendar::from_lines(&["2020-01-02", "2020-01-03"]).unwrap()
+    }
+
+    fn tick(time: &str, current: f64, high: f64, low: f64, volume: f64) -> Tick {
+        let n = |v: f64| Numeric::from_f64(v);
+        Tick {
+            code: None,
+            time: time.to_owned(),
+            current: n(current),
+            high: n(high),
+            low: n(low),
+            volume: n(volume),
+            money: n(volume * 10.0),
+            position: None,
+            a1_v: None,
+            a2_v: None,
+            a3_v: None,
+            a4_v: None,
+            a5_v: None,
+            a1_p: None,
+            a2_p: None,
+            a3_p: None,
+            a4_p: None,
+            a5_p: None,
+            b1_v: None,
+            b2_v: None,
+            b3_v: None,
+            b4_v: None,
+            b5_v: None,
+            b1_p: None,
+            b2_p: None,
+            b3_p: None,
+            b4_p: None,
+            b5_p: None,
+            extra: Default::default(),
+        }
+    }
+
+    fn ticks() -> Vec<Tick> {
+        vec![
+            tick("20200102092500.0", 10.0, 10.0, 10.0, 100.0),
+            tick("20200102093003.0", 10.1, 10.1, 10.0, 150.0),
+            tick("20200102093057.0", 10.05, 10.2, 10.0, 200.0),
+            tick("20200102093103.0", 10.0, 10.2, 9.9, 260.0),
+            tick("20200102112959.0", 10.3, 10.3, 9.9, 300.0),
+            tick("20200102130002.0", 10.4, 10.4, 9.9, 330.0),
+            // new trade day restarts cumulative values
+            tick("20200103093000.0", 11.0, 11.0, 11.0, 50.0),
+        ]
+    }
+
+    #[test]
+    fn test_parse_tick_time() {
+        let expected = NaiveDate::from_ymd_opt(2020, 1, 2).unwrap().and_hms_milli_opt(9, 30, 0, 500).unwrap();
+        assert_eq!(expected, parse_tick_time("20200102093000.5").unwrap());
+        assert_eq!(expected, parse_tick_time("2020-01-02 09:30:00.500").unwrap());
+        assert!(parse_tick_time("09:30").is_err());
+    }
+
+    #[test]
+    fn test_time_bars() {
+        let bars = ticks_to_bars(calendar(), Market::Stock, BarInterval::Time(Duration::minutes(1)), &ticks()).unwrap();
+        let dates: Vec<&str> = bars.iter().map(|b| b.date.as_str()).collect();
+        assert_eq!(
+            vec![
+                "2020-01-02 09:31:00",
+                "2020-01-02 09:32:00",
+                "2020-01-02 11:30:00",
+                "2020-01-02 13:01:00",
+                "2020-01-03 09:31:00"
+            ],
+            dates
+        );
+        let first = &bars[0];
+        assert_eq!((10.0, 10.05, 10.2, 10.0), (first.open.to_f64(), first.close.to_f64(), first.high.to_f64(), first.low.to_f64()));
+        assert_eq!(200.0, first.volume.to_f64());
+        assert_eq!(2000.0, first.money.to_f64());
+        assert_eq!(9.9, bars[1].low.to_f64());
+        assert_eq!(60.0, bars[1].volume.to_f64());
+        assert_eq!(50.0, bars[4].volume.to_f64());
+
+        let hour = ticks_to_bars(calendar(), Market::Stock, BarInterval::Time(Duration::minutes(60)), &ticks()).unwrap();
+        assert_eq!(vec![260.0, 40.0, 30.0, 50.0], hour.iter().map(|b| b.volume.to_f64()).collect::<Vec<_>>());
+        assert_eq!("2020-01-02 10:30:00", hour[0].date);
+        assert_eq!("2020-01-02 14:00:00", hour[2].date);
+    }
+
+    #[test]
+    fn test_volume_bars() {
+        let bars = ticks_to_bars(calendar(), Market::Stock, BarInterval::Volume(150.0), &ticks()).unwrap();
+        let volumes: Vec<f64> = bars.iter().map(|b| b.volume.to_f64()).collect();
+        assert_eq!(vec![150.0, 150.0, 80.0], volumes);
+        assert_eq!("2020-01-02 09:30:03", bars[0].date);
+        let bars = ticks_to_bars(calendar(), Market::Stock, BarInterval::Money(3000.0), &ticks()).unwrap();
+        assert_eq!(vec![3000.0, 800.0], bars.iter().map(|b| b.money.to_f64()).collect::<Vec<_>>());
+    }
+
+    fn minute(date: &str, open: f64, close: f64, volume: f64, oi: Option<f64>) -> Price {
+        let n = |v: f64| Numeric::from_f64(v);
+        Price {
+            date: date.to_owned(),
+            open: n(open),
+            close: n(close),
+            high: n(open.max(close)),
+            low: n(open.min(close)),
+            volume: n(volume),
+            money: n(volume * close),
+            paused: Some(0),
+            high_limit: None,
+            low_limit: None,
+            avg: None,
+            pre_close: None,
+            open_interest: oi.map(n),
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_resample_stock() {
+        let bars = vec![
+            minute("2020-01-02 09:31:00", 10.0, 10.2, 100.0, None),
+            minute("2020-01-02 09:32:00", 10.2, 9.8, 100.0, None),
+            minute("2020-01-02 09:33:00", 9.8, 9.9, 100.0, None),
+            minute("2020-01-02 11:30:00", 10.5, 10.4, 100.0, None),
+            minute("2020-01-02 13:01:00", 10.4, 10.6, 100.0, None),
+            minute("2020-01-02 13:02:00", 10.6, 10.7, 100.0, None),
+        ];
+        let resampler = Resampler::new(calendar(), Market::Stock, ResampleRule::Time(Duration::minutes(3)));
+        let resampled = resampler.resample(&bars).unwrap();
+        let dates: Vec<&str> = resampled.iter().map(|b| b.date.as_str()).collect();
+        assert_eq!(vec!["2020-01-02 09:33:00", "2020-01-02 11:30:00", "2020-01-02 13:03:00"], dates);
+        let first = &resampled[0];
+        assert_eq!((10.0, 9.9, 10.2, 9.8), (first.open.to_f64(), first.close.to_f64(), first.high.to_f64(), first.low.to_f64()));
+        assert_eq!(300.0, first.volume.to_f64());
+        // 13:03 bar is partial
+        assert_eq!(2, resampler.drop_partial(true).resample(&bars).unwrap().len());
+
+        let sessions = Resampler::new(calendar(), Market::Stock, ResampleRule::Session).resample(&bars).unwrap();
+        assert_eq!(vec![400.0, 200.0], sessions.iter().map(|b| b.volume.to_f64()).collect::<Vec<_>>());
+        assert_eq!("2020-01-02 15:00:00", sessions[1].date);
+    }
+
+    #[test]
+    fn test_resample_night_session() {
+        let market = Market::CommodityFutures { night_end: Some(chrono::NaiveTime::from_hms_opt(23, 0, 0).unwrap()) };
+        let bars = vec![
+            minute("2020-01-02 21:01:00", 100.0, 101.0, 10.0, Some(500.0)),
+            minute("2020-01-02 23:00:00", 101.0, 102.0, 10.0, Some(510.0)),
+            minute("2020-01-03 09:01:00", 102.0, 99.0, 10.0, Some(520.0)),
+            minute("2020-01-03 15:00:00", 99.0, 98.0, 10.0, Some(530.0)),
+        ];
+        let days = Resampler::new(calendar(), market, ResampleRule::TradeDay).resample(&bars).unwrap();
+        // the night session of 2020-01-02 belongs to 2020-01-03
+        assert_eq!(1, days.len());
+        assert_eq!("2020-01-03", days[0].date);
+        assert_eq!((100.0, 98.0, 102.0, 98.0), (days[0].open.to_f64(), days[0].close.to_f64(), days[0].high.to_f64(), days[0].low.to_f64()));
+        assert_eq!(Some(530.0), days[0].open_interest.as_ref().map(Numeric::to_f64));
+        let hours = Resampler::new(calendar(), market, ResampleRule::Time(Duration::minutes(90))).resample(&bars).unwrap();
+        let dates: Vec<&str> = hours.iter().map(|b| b.date.as_str()).collect();
+        assert_eq!(vec!["2020-01-02 22:30:00", "2020-01-02 23:00:00", "2020-01-03 10:15:00", "2020-01-03 15:00:00"], dates);
+    }
+
+    #[test]
+    fn test_diff_bars() {
+        let built = ticks_to_bars(calendar(), Market::Stock, BarInterval::Time(Duration::minutes(1)), &ticks()).unwrap();
+        let mut reference = built[..2].to_vec();
+        reference[1].volume = Numeric::from_f64(61.0);
+        let diffs = diff_bars(&built, &reference, 1e-6);
+        assert_eq!("volume", diffs[0].field);
+        assert_eq!(Some(60.0), diffs[0].built);
+        assert_eq!(3, diffs.iter().filter(|d| d.field == "bar" && d.reference.is_none()).count());
+        assert!(diff_bars(&built, &built, 1e-6).is_empty());
+    }
+}
@@ -0,0 +1,503 @@
+//! Bulk download
+//!
+//! Downloads prices, money flows and margin trading data of many
+//! securities over a date range, resumable after a crash or when
+//! the daily quota runs out.
+//!
+//! All requests are planned up front into chunks of at most 1000
+//! trade days per security and dataset, and written to
+//! `<dir>/manifest.json`. Each chunk is saved as
+//! `<dir>/<dataset>/<code>.<date>.<end_date>.csv` and marked done in
+//! the manifest once written, so running the same job again only
+//! executes the pending chunks.
+//!
+//! Index constituents are resolved over the whole range with
+//! `IndexHistoryBuilder`, and each security is only downloaded for
+//! the trade days it was a member.
+use crate::store::MAX_TRADE_DAYS_PER_REQUEST;
+use crate::{
+    Error, GetIndustryStocks, GetMoneyFlow, GetMtss, GetPricePeriod, GetQueryCount, GetTradeDays,
+    IndexHistoryBuilder, JqdataClient, Result,
+};
+use futures::future;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use serde_derive::*;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// securities to download
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeSet {
+    /// constituents of index, each over the days it was a member
+    Index(String),
+    /// stocks of industry at start or end date
+    Industry(String),
+    Codes(Vec<String>),
+}
+
+/// downloadable datasets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dataset {
+    /// get_price_period with unit of the job
+    Price,
+    /// get_money_flow
+    MoneyFlow,
+    /// get_mtss
+    Mtss,
+}
+
+impl Dataset {
+    fn dir_name(self, unit: &str) -> String {
+        match self {
+            Dataset::Price => format!("price_{}", unit),
+            Dataset::MoneyFlow => "money_flow".to_owned(),
+            Dataset::Mtss => "mtss".to_owned(),
+        }
+    }
+}
+
+/// BulkSpec
+///
+/// what to download, dates are inclusive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BulkSpec {
+    pub codes: CodeSet,
+    pub datasets: Vec<Dataset>,
+    /// unit of prices, such as 1d or 1m
+    pub unit: String,
+    pub start_date: String,
+    pub end_date: String,
+}
+
+/// one planned request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chunk {
+    pub dataset: Dataset,
+    pub code: String,
+    pub date: String,
+    pub end_date: String,
+    pub done: bool,
+}
+
+/// Manifest
+///
+/// spec and planned chunks of a job
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub spec: BulkSpec,
+    pub chunks: Vec<Chunk>,
+}
+
+impl Manifest {
+    pub fn pending(&self) -> usize {
+        self.chunks.iter().filter(|c| !c.done).count()
+    }
+}
+
+/// summary of one run
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BulkReport {
+    /// chunks completed in this run
+    pub completed: usize,
+    /// chunks left for later runs
+    pub pending: usize,
+    pub rows: usize,
+    /// the run stopped because remaining quota fell below reserve
+    pub quota_exhausted: bool,
+}
+
+/// BulkDownload
+///
+/// executor of bulk download jobs in one directory
+#[derive(Clone)]
+pub struct BulkDownload {
+    client: JqdataClient,
+    dir: PathBuf,
+    concurrency: usize,
+    quota_reserve: Option<i32>,
+}
+
+impl BulkDownload {
+    /// job directory is created if not exists
+    pub fn new<P: Into<PathBuf>>(client: JqdataClient, dir: P) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(BulkDownload {
+            client,
+            dir,
+            concurrency: 4,
+            quota_reserve: None,
+        })
+    }
+
+    /// number of requests in flight, 4 by default
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// stop the run when remaining query count is below reserve,
+    /// quota is not checked by default
+    pub fn quota_reserve(mut self, reserve: i32) -> Self {
+        self.quota_reserve = Some(reserve);
+        self
+    }
+
+    fn manifest_path(&self) -> PathBuf {
+        self.dir.join(MANIFEST_FILE)
+    }
+
+    /// saved manifest of the directory
+    pub fn load_manifest(&self) -> Result<Option<Manifest>> {
+        let path = self.manifest_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+
+    fn save_manifest(&self, manifest: &Manifest) -> Result<()> {
+        let path = self.manifest_path();
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(manifest)?)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// resolve codes and trade days and save a new manifest,
+    /// replacing the existing one
+    pub async fn plan(&self, spec: BulkSpec) -> Result<Manifest> {
+        let trade_days = self
+            .client
+            .execute(GetTradeDays {
+                date: spec.start_date.clone(),
+                end_date: Some(spec.end_date.clone()),
+            })
+            .await?;
+        let whole = vec![(0, trade_days.len())];
+        // ranges of trade day indexes to download by code
+        let ranges: BTreeMap<String, Vec<(usize, usize)>> = match spec.codes {
+            CodeSet::Index(ref code) => {
+                let history = IndexHistoryBuilder::new(self.client.clone())
+                    .build(code, &spec.start_date, &spec.end_date)
+                    .await?;
+                let mut ranges: BTreeMap<String, Vec<(usize, usize)>> = BTreeMap::new();
+                for (i, snapshot) in history.snapshots.iter().enumerate() {
+                    let start = trade_days.partition_point(|d| *d < snapshot.date);
+                    let end = match history.snapshots.get(i + 1) {
+                        Some(next) => trade_days.partition_point(|d| *d < next.date),
+                        None => trade_days.len(),
+                    };
+                    for member in &snapshot.members {
+                        let member_ranges = ranges.entry(member.clone()).or_default();
+                        match member_ranges.last_mut() {
+                            Some(last) if last.1 == start => last.1 = end,
+                            _ => member_ranges.push((start, end)),
+                        }
+                    }
+                }
+                ranges
+            }
+            CodeSet::Industry(ref code) => {
+                let mut codes = BTreeSet::new();
+                for date in &[&spec.start_date, &spec.end_date] {
+                    let members = self
+                        .client
+                        .execute(GetIndustryStocks {
+                            code: code.to_owned(),
+                            date: date.to_string(),
+                        })
+                        .await?;
+                    codes.extend(members);
+                }
+                codes.into_iter().map(|code| (code, whole.clone())).collect()
+            }
+            CodeSet::Codes(ref codes) => codes.iter().map(|code| (code.clone(), whole.clone())).collect(),
+        };
+        let mut chunks = Vec::new();
+        for dataset in &spec.datasets {
+            for (code, code_ranges) in &ranges {
+                for &(start, end) in code_ranges {
+                    for days in trade_days[start..end].chunks(MAX_TRADE_DAYS_PER_REQUEST) {
+                        chunks.push(Chunk {
+                            dataset: *dataset,
+                            code: code.to_owned(),
+                            date: days[0].clone(),
+                            end_date: days[days.len() - 1].clone(),
+                            done: false,
+                        });
+                    }
+                }
+            }
+        }
+        let manifest = Manifest { spec, chunks };
+        self.save_manifest(&manifest)?;
+        Ok(manifest)
+    }
+
+    /// resume the saved job if it has the same spec,
+    /// otherwise plan a new one, then run pending chunks
+    pub async fn download(&self, spec: BulkSpec) -> Result<BulkReport> {
+        match self.load_manifest()? {
+            Some(ref manifest) if manifest.spec == spec => (),
+            _ => {
+                self.plan(spec).await?;
+            }
+        }
+        self.resume().await
+    }
+
+    /// run pending chunks of the saved job
+    ///
+    /// progress is saved after each completed request. A failed
+    /// request stops starting new ones, requests in flight are
+    /// still completed, and the first failure is returned
+    pub async fn resume(&self) -> Result<BulkReport> {
+        let mut manifest = self
+            .load_manifest()?
+            .ok_or_else(|| Error::Client(format!("no manifest in {}", self.dir.display())))?;
+        let pending: Vec<usize> = (0..manifest.chunks.len())
+            .filter(|idx| !manifest.chunks[*idx].done)
+            .collect();
+        let mut report = BulkReport::default();
+        if !self.has_quota().await? {
+            report.quota_exhausted = true;
+            report.pending = manifest.pending();
+            return Ok(report);
+        }
+        let unit = manifest.spec.unit.clone();
+        let chunks = manifest.chunks.clone();
+        let stopped = Cell::new(false);
+        let mut results = stream::iter(pending)
+            .take_while(|_| future::ready(!stopped.get()))
+            .map(|idx| {
+                let (unit, chunk) = (&unit, &chunks[idx]);
+                async move { (idx, self.fetch(unit, chunk).await) }
+            })
+            .buffer_unordered(self.concurrency);
+        let mut failure = None;
+        let mut finished = 0;
+        while let Some((idx, result)) = results.next().await {
+            match result {
+                Ok(rows) => {
+                    manifest.chunks[idx].done = true;
+                    report.completed += 1;
+                    report.rows += rows;
+                    self.save_manifest(&manifest)?;
+                }
+                Err(e) => {
+                    stopped.set(true);
+                    failure = failure.or(Some(e));
+                }
+            }
+            finished += 1;
+            // quota is checked once per `concurrency` completed requests
+            if !stopped.get() && finished % self.concurrency == 0 {
+                match self.has_quota().await {
+                    Ok(true) => (),
+                    Ok(false) => {
+                        report.quota_exhausted = true;
+                        stopped.set(true);
+                    }
+                    Err(e) => {
+                        stopped.set(true);
+                        failure = failure.or(Some(e));
+                    }
+                }
+            }
+        }
+        if let Some(e) = failure {
+            return Err(e);
+        }
+        report.pending = manifest.pending();
+        Ok(report)
+    }
+
+    /// whether remaining query count is at least the reserve,
+    /// always true without a reserve
+    async fn has_quota(&self) -> Result<bool> {
+        match self.quota_reserve {
+            Some(reserve) => Ok(self.client.execute(GetQueryCount {}).await? >= reserve),
+            None => Ok(true),
+        }
+    }
+
+    /// path of the csv file of chunk
+    pub fn chunk_path(&self, unit: &str, chunk: &Chunk) -> PathBuf {
+        self.dir
+            .join(chunk.dataset.dir_name(unit))
+            .join(format!("{}.{}.{}.csv", chunk.code, chunk.date, chunk.end_date))
+    }
+
+    async fn fetch(&self, unit: &str, chunk: &Chunk) -> Result<usize> {
+        let path = self.chunk_path(unit, chunk);
+        let code = chunk.code.clone();
+        let date = chunk.date.clone();
+        let end_date = chunk.end_date.clone();
+        match chunk.dataset {
+            Dataset::Price => {
+                let rows = self
+                    .client
+                    .execute(GetPricePeriod {
+                        code,
+                        unit: unit.to_owned(),
+                        date,
+                        end_date,
+                        fq_ref_date: None,
+                    })
+                    .await?;
+                write_rows(&path, &rows)
+            }
+            Dataset::MoneyFlow => {
+                let rows = self.client.execute(GetMoneyFlow { code, date, end_date }).await?;
+                write_rows(&path, &rows)
+            }
+            Dataset::Mtss => {
+                let rows = self.client.execute(GetMtss { code, date, end_date }).await?;
+                write_rows(&path, &rows)
+            }
+        }
+    }
+}
+
+/// write rows to a temporary file and move it into place,
+/// so a crash never leaves a partial chunk file
+fn write_rows<T: Serialize>(path: &Path, rows: &[T]) -> Result<usize> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    let mut writer = csv::Writer::from_path(&tmp)?;
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    drop(writer);
+    std::fs::rename(tmp, path)?;
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FakeResponse, FakeTransport};
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    fn spec() -> BulkSpec {
+        BulkSpec {
+            codes: CodeSet::Index("000300.XSHG".to_owned()),
+            datasets: vec![Dataset::Price, Dataset::Mtss],
+            unit: "1d".to_owned(),
+            start_date: "2020-01-01".to_owned(),
+            end_date: "2020-01-03".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_bulk_download_resumes_after_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        block_on(async {
+            let fake = FakeTransport::new();
+            fake.respond("get_index_stocks", json!({}), FakeResponse::lines(&["000001.XSHE", "600000.XSHG"]))
+                .respond("get_trade_days", json!({}), FakeResponse::lines(&["2020-01-02", "2020-01-03"]))
+                .respond(
+                    "get_price_period",
+                    json!({}),
+                    FakeResponse::csv(
+                        &["date", "open", "close", "high", "low", "volume", "money"],
+                        &[&["2020-01-02", "10", "11", "11", "10", "100", "1000"]],
+                    ),
+                )
+                .respond(
+                    "get_mtss",
+                    json!({"code": "000001.XSHE"}),
+                    FakeResponse::csv(
+                        &["date", "sec_code", "fin_value", "fin_buy_value", "fin_refund_value", "sec_value", "sec_sell_value", "sec_refund_value", "fin_sec_value"],
+                        &[&["2020-01-02", "000001.XSHE", "1", "1", "1", "1", "1", "1", "1"]],
+                    ),
+                )
+                .respond_once("get_mtss", json!({"code": "600000.XSHG"}), FakeResponse::server_error("quota exceeded"))
+                .respond("get_query_count", json!({}), FakeResponse::single(1000));
+            let client = JqdataClient::with_transport(fake.clone(), "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            let bulk = BulkDownload::new(client, dir.path()).unwrap().concurrency(2).quota_reserve(10);
+            assert!(bulk.download(spec()).await.is_err());
+            let manifest = bulk.load_manifest().unwrap().unwrap();
+            assert_eq!(4, manifest.chunks.len());
+            assert_eq!(1, manifest.pending());
+            assert!(bulk.chunk_path("1d", &manifest.chunks[0]).exists());
+
+            fake.respond(
+                "get_mtss",
+                json!({"code": "600000.XSHG"}),
+                FakeResponse::csv(&["date", "sec_code"], &[]),
+            );
+            fake.clear_requests();
+            let report = bulk.download(spec()).await.unwrap();
+            assert_eq!(BulkReport { completed: 1, pending: 0, rows: 0, quota_exhausted: false }, report);
+            assert_eq!(1, fake.requests_of("get_mtss").len());
+            assert!(fake.requests_of("get_index_stocks").is_empty());
+        });
+    }
+
+    #[test]
+    fn test_plan_covers_index_members_of_each_day() {
+        let dir = tempfile::tempdir().unwrap();
+        block_on(async {
+            let fake = FakeTransport::new();
+            let days = ["2020-01-02", "2020-01-03", "2020-01-06"];
+            fake.respond("get_trade_days", json!({}), FakeResponse::lines(&days))
+                .respond("get_index_stocks", json!({"date": "2020-01-02"}), FakeResponse::lines(&["000001.XSHE", "000002.XSHE"]))
+                .respond("get_index_stocks", json!({}), FakeResponse::lines(&["000001.XSHE", "000003.XSHE"]));
+            let client = JqdataClient::with_transport(fake.clone(), "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            let bulk = BulkDownload::new(client, dir.path()).unwrap();
+            let mut spec = spec();
+            spec.datasets = vec![Dataset::Price];
+            spec.end_date = "2020-01-06".to_owned();
+            let manifest = bulk.plan(spec).await.unwrap();
+            let ranges: Vec<(&str, &str, &str)> = manifest
+                .chunks
+                .iter()
+                .map(|c| (c.code.as_str(), c.date.as_str(), c.end_date.as_str()))
+                .collect();
+            assert_eq!(
+                vec![
+                    ("000001.XSHE", "2020-01-02", "2020-01-06"),
+                    ("000002.XSHE", "2020-01-02", "2020-01-02"),
+                    ("000003.XSHE", "2020-01-03", "2020-01-06"),
+                ],
+                ranges
+            );
+        });
+    }
+
+    #[test]
+    fn test_bulk_download_stops_at_quota_reserve() {
+        let dir = tempfile::tempdir().unwrap();
+        block_on(async {
+            let fake = FakeTransport::new();
+            fake.respond("get_trade_days", json!({}), FakeResponse::lines(&["2020-01-02"]))
+                .respond("get_query_count", json!({}), FakeResponse::single(5));
+            let client = JqdataClient::with_transport(fake.clone(), "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            let bulk = BulkDownload::new(client, dir.path()).unwrap().quota_reserve(10);
+            let mut spec = spec();
+            spec.codes = CodeSet::Codes(vec!["000001.XSHE".to_owned()]);
+            let report = bulk.download(spec).await.unwrap();
+            assert!(report.quota_exhausted);
+            assert_eq!(2, report.pending);
+            assert!(fake.requests_of("get_price_period").is_empty());
+        });
+    }
+}
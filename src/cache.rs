@@ -0,0 +1,374 @@
+//! Cache
+//!
+//! Response cache implemented as a transport wrapper. Responses are
+//! keyed by the request body without token, and stored in a
+//! `CacheStore` (in-memory or on-disk).
+//!
+//! Expiration is decided by `CachePolicy`:
+//! requests whose date parameters are all in the past are cached
+//! permanently because history does not change, requests touching
+//! today or the future expire quickly, other requests use per-method
+//! or default TTL. Real-time and quota methods are never cached, and
+//! error responses are never stored.
+use crate::calendar::beijing_today;
+use crate::cassette::redact_request;
+use crate::{Error, Result, Transport};
+use chrono::NaiveDate;
+use futures::future::BoxFuture;
+use serde_derive::*;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// cached response body with optional expiration time,
+/// in seconds since unix epoch
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CacheEntry {
+    pub body: String,
+    pub expires_at: Option<u64>,
+}
+
+impl CacheEntry {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.map(|t| t <= now).unwrap_or(false)
+    }
+}
+
+/// storage backend of cache
+pub trait CacheStore: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<CacheEntry>>;
+
+    fn put(&self, key: &str, entry: CacheEntry) -> Result<()>;
+
+    fn remove(&self, key: &str) -> Result<()>;
+}
+
+/// MemoryCache
+///
+/// cache store in process memory
+#[derive(Debug, Default)]
+pub struct MemoryCache {
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl MemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CacheStore for MemoryCache {
+    fn get(&self, key: &str) -> Result<Option<CacheEntry>> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) -> Result<()> {
+        self.entries.lock().unwrap().insert(key.to_owned(), entry);
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.entries.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+/// DiskCache
+///
+/// cache store in a directory, one json file per entry.
+/// file name is the hash of key, and the key is stored
+/// in file to detect hash collisions
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DiskEntry {
+    key: String,
+    #[serde(flatten)]
+    entry: CacheEntry,
+}
+
+impl DiskCache {
+    /// open cache in directory, created if not exists
+    pub fn open<P: Into<PathBuf>>(dir: P) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(DiskCache { dir })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+            (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        });
+        self.dir.join(format!("{:016x}.json", hash))
+    }
+}
+
+impl CacheStore for DiskCache {
+    fn get(&self, key: &str) -> Result<Option<CacheEntry>> {
+        let content = match std::fs::read(self.path(key)) {
+            Ok(content) => content,
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let disk_entry: DiskEntry = serde_json::from_slice(&content)?;
+        if disk_entry.key != key {
+            return Ok(None);
+        }
+        Ok(Some(disk_entry.entry))
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) -> Result<()> {
+        let path = self.path(key);
+        let tmp = path.with_extension("tmp");
+        let disk_entry = DiskEntry {
+            key: key.to_owned(),
+            entry,
+        };
+        std::fs::write(&tmp, serde_json::to_vec(&disk_entry)?)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        match std::fs::remove_file(self.path(key)) {
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            other => other.map_err(Into::into),
+        }
+    }
+}
+
+/// how long a response is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiration {
+    Never,
+    After(Duration),
+    NoCache,
+}
+
+/// CachePolicy
+///
+/// decides expiration of responses by method and parameters
+#[derive(Debug, Clone)]
+pub struct CachePolicy {
+    default_ttl: Duration,
+    intraday_ttl: Duration,
+    method_ttl: HashMap<String, Duration>,
+    no_cache: HashSet<String>,
+}
+
+/// methods whose responses change in real time
+const NO_CACHE_METHODS: &[&str] = &[
+    "get_token",
+    "get_current_token",
+    "get_query_count",
+    "get_current_tick",
+    "get_current_ticks",
+];
+
+/// date parameters used to decide whether request is historical
+const DATE_PARAMS: &[&str] = &["date", "end_date"];
+
+/// methods with optional end_date
+const OPEN_ENDED_METHODS: &[&str] = &["get_trade_days", "get_price"];
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        let mut method_ttl = HashMap::new();
+        // new trade days are appended once a year
+        method_ttl.insert("get_all_trade_days".to_owned(), Duration::from_secs(24 * 3600));
+        CachePolicy {
+            default_ttl: Duration::from_secs(3600),
+            intraday_ttl: Duration::from_secs(60),
+            method_ttl,
+            no_cache: NO_CACHE_METHODS.iter().map(|m| (*m).to_owned()).collect(),
+        }
+    }
+}
+
+impl CachePolicy {
+    /// TTL of requests without date parameters
+    pub fn default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// TTL of requests whose date range includes today or future
+    pub fn intraday_ttl(mut self, ttl: Duration) -> Self {
+        self.intraday_ttl = ttl;
+        self
+    }
+
+    /// TTL of given method, overrides date based rules
+    pub fn method_ttl(mut self, method: &str, ttl: Duration) -> Self {
+        self.method_ttl.insert(method.to_owned(), ttl);
+        self
+    }
+
+    /// never cache given method
+    pub fn no_cache(mut self, method: &str) -> Self {
+        self.no_cache.insert(method.to_owned());
+        self
+    }
+
+    /// expiration of request, with current date in Beijing time
+    pub fn expiration(&self, request: &Value, today: NaiveDate) -> Expiration {
+        let method = request.get("method").and_then(Value::as_str).unwrap_or_default();
+        if self.no_cache.contains(method) {
+            return Expiration::NoCache;
+        }
+        if let Some(ttl) = self.method_ttl.get(method) {
+            return Expiration::After(*ttl);
+        }
+        // end date defaults to today on server side if not specified
+        if OPEN_ENDED_METHODS.contains(&method) && request.get("end_date").is_none() {
+            return Expiration::After(self.intraday_ttl);
+        }
+        let dates: Vec<NaiveDate> = DATE_PARAMS
+            .iter()
+            .filter_map(|p| request.get(*p).and_then(Value::as_str))
+            .filter_map(parse_date)
+            .collect();
+        if dates.is_empty() {
+            Expiration::After(self.default_ttl)
+        } else if dates.iter().all(|d| *d < today) {
+            Expiration::Never
+        } else {
+            Expiration::After(self.intraday_ttl)
+        }
+    }
+}
+
+/// parse date part of "2020-01-02" or "2020-01-02 10:00:00"
+fn parse_date(s: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(s.get(..10)?, "%Y-%m-%d").ok()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// CachingTransport
+///
+/// transport serving responses from cache store,
+/// and forwarding misses to inner transport
+pub struct CachingTransport {
+    inner: Arc<dyn Transport>,
+    store: Arc<dyn CacheStore>,
+    policy: CachePolicy,
+}
+
+impl CachingTransport {
+    pub fn new<T, S>(inner: T, store: S) -> Self
+    where
+        T: Transport + 'static,
+        S: CacheStore + 'static,
+    {
+        CachingTransport {
+            inner: Arc::new(inner),
+            store: Arc::new(store),
+            policy: CachePolicy::default(),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: CachePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl Transport for CachingTransport {
+    fn send(&self, body: String) -> BoxFuture<'_, Result<Vec<u8>>> {
+        Box::pin(async move {
+            let request = redact_request(&body)?;
+            let expiration = self.policy.expiration(&request, beijing_today());
+            if expiration == Expiration::NoCache {
+                return self.inner.send(body).await;
+            }
+            let key = request.to_string();
+            let now = unix_now();
+            if let Some(entry) = self.store.get(&key)? {
+                if !entry.is_expired(now) {
+                    return Ok(entry.body.into_bytes());
+                }
+                self.store.remove(&key)?;
+            }
+            let response = self.inner.send(body).await?;
+            if !response.starts_with(b"error") {
+                let body = String::from_utf8(response).map_err(Error::from)?;
+                let expires_at = match expiration {
+                    Expiration::After(ttl) => Some(now + ttl.as_secs()),
+                    _ => None,
+                };
+                self.store.put(
+                    &key,
+                    CacheEntry {
+                        body: body.clone(),
+                        expires_at,
+                    },
+                )?;
+                return Ok(body.into_bytes());
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FakeResponse, FakeTransport, GetIndexWeights, JqdataClient};
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[test]
+    fn test_cache_policy_expiration() {
+        let policy = CachePolicy::default();
+        let today = NaiveDate::from_ymd_opt(2020, 6, 1).unwrap();
+        let past = json!({"method": "get_price_period", "code": "000001.XSHE", "unit": "1d", "date": "2020-01-01", "end_date": "2020-05-29"});
+        assert_eq!(Expiration::Never, policy.expiration(&past, today));
+        let current = json!({"method": "get_price_period", "code": "000001.XSHE", "unit": "1d", "date": "2020-01-01", "end_date": "2020-06-01 15:00:00"});
+        assert_eq!(Expiration::After(Duration::from_secs(60)), policy.expiration(&current, today));
+        let open_ended = json!({"method": "get_trade_days", "date": "2020-01-01"});
+        assert_eq!(Expiration::After(Duration::from_secs(60)), policy.expiration(&open_ended, today));
+        let undated = json!({"method": "get_security_info", "code": "000001.XSHE"});
+        assert_eq!(Expiration::After(Duration::from_secs(3600)), policy.expiration(&undated, today));
+        let quota = json!({"method": "get_query_count"});
+        assert_eq!(Expiration::NoCache, policy.expiration(&quota, today));
+    }
+
+    #[test]
+    fn test_caching_transport() {
+        let dir = tempfile::tempdir().unwrap();
+        let command = || GetIndexWeights {
+            code: "000300.XSHG".to_owned(),
+            date: "2020-01-02".to_owned(),
+        };
+        block_on(async {
+            let fake = FakeTransport::new();
+            fake.respond_any(
+                "get_index_weights",
+                FakeResponse::csv(
+                    &["code", "display_name", "date", "weight"],
+                    &[&["600519.XSHG", "贵州茅台", "2020-01-02", "5.2"]],
+                ),
+            );
+            let cache = CachingTransport::new(fake.clone(), DiskCache::open(dir.path()).unwrap());
+            let client = JqdataClient::with_transport(cache, "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            let first = client.execute(command()).await.unwrap();
+            let second = client.execute(command()).await.unwrap();
+            assert_eq!(first.len(), second.len());
+            assert_eq!(1, fake.requests_of("get_index_weights").len());
+            assert_eq!(1, std::fs::read_dir(dir.path()).unwrap().count());
+        });
+    }
+}
@@ -0,0 +1,429 @@
+//! Trading calendar
+//!
+//! Trade days from get_all_trade_days with trade-day arithmetic
+//! and trading sessions, usable offline once saved to disk.
+//!
+//! Sessions are in Beijing time. Night sessions of futures belong
+//! to the next trade day: the session starting at 21:00 on Friday
+//! is part of Monday. Exchanges do not open night sessions before
+//! holidays, so only a trade day following the previous calendar
+//! day, or a Monday following a Friday, has a night session.
+use crate::{Error, GetAllTradeDays, JqdataClient, Result};
+use chrono::{Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+use std::path::Path;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+/// call auctions start at most this long before a session
+const AUCTION_MINUTES: i64 = 15;
+/// snapshots are accepted this long after the end of a session
+const LATE_MINUTES: i64 = 1;
+
+fn parse_date(s: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(s.trim(), DATE_FORMAT)
+        .map_err(|e| Error::Client(format!("invalid trade day {}: {}", s, e)))
+}
+
+fn hm(hour: u32, min: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, min, 0).unwrap()
+}
+
+/// current date in Beijing time
+pub fn beijing_today() -> NaiveDate {
+    beijing_now().date()
+}
+
+/// current time in Beijing, the time zone of trading sessions
+pub fn beijing_now() -> NaiveDateTime {
+    let offset = FixedOffset::east_opt(8 * 3600).unwrap();
+    Utc::now().with_timezone(&offset).naive_local()
+}
+
+/// Market
+///
+/// kinds of trading hours
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Market {
+    /// stocks, funds and indexes: 09:30-11:30, 13:00-15:00
+    Stock,
+    /// index futures: 09:30-11:30, 13:00-15:00
+    IndexFutures,
+    /// treasury futures: 09:30-11:30, 13:00-15:15
+    TreasuryFutures,
+    /// commodity futures: 09:00-10:15, 10:30-11:30, 13:30-15:00,
+    /// and a night session from 21:00 until `night_end` if set,
+    /// e.g. 23:00, 01:00 or 02:30
+    CommodityFutures { night_end: Option<NaiveTime> },
+}
+
+impl Market {
+    /// day sessions as (start, end) times
+    fn day_sessions(self) -> Vec<(NaiveTime, NaiveTime)> {
+        match self {
+            Market::Stock | Market::IndexFutures => vec![(hm(9, 30), hm(11, 30)), (hm(13, 0), hm(15, 0))],
+            Market::TreasuryFutures => vec![(hm(9, 30), hm(11, 30)), (hm(13, 0), hm(15, 15))],
+            Market::CommodityFutures { .. } => vec![
+                (hm(9, 0), hm(10, 15)),
+                (hm(10, 30), hm(11, 30)),
+                (hm(13, 30), hm(15, 0)),
+            ],
+        }
+    }
+}
+
+/// continuous trading period
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Session {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+impl Session {
+    pub fn contains(&self, time: NaiveDateTime) -> bool {
+        self.start <= time && time <= self.end
+    }
+}
+
+/// TradingCalendar
+///
+/// sorted trade days
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TradingCalendar {
+    days: Vec<NaiveDate>,
+}
+
+impl TradingCalendar {
+    /// days are sorted and deduplicated
+    pub fn new(mut days: Vec<NaiveDate>) -> Self {
+        days.sort();
+        days.dedup();
+        TradingCalendar { days }
+    }
+
+    /// parse "%Y-%m-%d" dates, as returned by get_all_trade_days
+    pub fn from_lines<S: AsRef<str>>(lines: &[S]) -> Result<Self> {
+        let days = lines
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|l| !l.trim().is_empty())
+            .map(parse_date)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::new(days))
+    }
+
+    /// fetch all trade days
+    pub async fn fetch(client: &JqdataClient) -> Result<Self> {
+        let lines = client.execute(GetAllTradeDays {}).await?;
+        Self::from_lines(&lines)
+    }
+
+    /// read calendar saved by `save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let lines: Vec<&str> = content.lines().collect();
+        Self::from_lines(&lines)
+    }
+
+    /// write one trade day per line
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut content = String::with_capacity(self.days.len() * 11);
+        for d in &self.days {
+            content.push_str(&d.format(DATE_FORMAT).to_string());
+            content.push('\n');
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, content)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// load calendar from file, fetching and saving it again
+    /// if the file is missing or does not cover today
+    pub async fn load_or_fetch<P: AsRef<Path>>(client: &JqdataClient, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            let calendar = Self::load(path)?;
+            if calendar.last().map(|d| d >= beijing_today()).unwrap_or(false) {
+                return Ok(calendar);
+            }
+        }
+        let calendar = Self::fetch(client).await?;
+        calendar.save(path)?;
+        Ok(calendar)
+    }
+
+    pub fn days(&self) -> &[NaiveDate] {
+        &self.days
+    }
+
+    pub fn first(&self) -> Option<NaiveDate> {
+        self.days.first().cloned()
+    }
+
+    pub fn last(&self) -> Option<NaiveDate> {
+        self.days.last().cloned()
+    }
+
+    /// index of the first trade day on or after date
+    fn lower_bound(&self, date: NaiveDate) -> usize {
+        self.days.partition_point(|d| *d < date)
+    }
+
+    pub fn is_trade_day(&self, date: NaiveDate) -> bool {
+        self.days.binary_search(&date).is_ok()
+    }
+
+    /// first trade day after date
+    pub fn next_trade_day(&self, date: NaiveDate) -> Option<NaiveDate> {
+        let idx = self.days.partition_point(|d| *d <= date);
+        self.days.get(idx).cloned()
+    }
+
+    /// last trade day before date
+    pub fn prev_trade_day(&self, date: NaiveDate) -> Option<NaiveDate> {
+        let idx = self.lower_bound(date);
+        idx.checked_sub(1).map(|i| self.days[i])
+    }
+
+    /// trade day n trade days after date, or before if n is negative
+    ///
+    /// if date is not a trade day, offset 1 is the next trade day
+    /// and offset -1 the previous one, offset 0 is None
+    pub fn offset(&self, date: NaiveDate, n: i64) -> Option<NaiveDate> {
+        let idx = self.lower_bound(date) as i64;
+        let target = if self.is_trade_day(date) || n < 0 {
+            idx + n
+        } else if n > 0 {
+            idx + n - 1
+        } else {
+            return None;
+        };
+        if target < 0 {
+            return None;
+        }
+        self.days.get(target as usize).cloned()
+    }
+
+    /// trade days between start and end, inclusive
+    pub fn trade_days_between(&self, start: NaiveDate, end: NaiveDate) -> &[NaiveDate] {
+        let from = self.lower_bound(start);
+        let to = self.days.partition_point(|d| *d <= end);
+        &self.days[from..to.max(from)]
+    }
+
+    /// number of trade days between start and end, inclusive
+    pub fn count_between(&self, start: NaiveDate, end: NaiveDate) -> usize {
+        self.trade_days_between(start, end).len()
+    }
+
+    /// last trade day of each group of consecutive days with the same key
+    fn group_ends<K: PartialEq, F: Fn(&NaiveDate) -> K>(&self, start: NaiveDate, end: NaiveDate, key: F) -> Vec<NaiveDate> {
+        let from = self.lower_bound(start);
+        let to = self.days.partition_point(|d| *d <= end);
+        (from..to)
+            .filter(|i| self.days.get(i + 1).map(|next| key(next) != key(&self.days[*i])).unwrap_or(false))
+            .map(|i| self.days[i])
+            .collect()
+    }
+
+    /// last trade day of each month between start and end,
+    /// the month of the last known trade day is not complete
+    /// and not included
+    pub fn month_ends(&self, start: NaiveDate, end: NaiveDate) -> Vec<NaiveDate> {
+        self.group_ends(start, end, |d| (d.year(), d.month()))
+    }
+
+    /// last trade day of each iso week between start and end
+    pub fn week_ends(&self, start: NaiveDate, end: NaiveDate) -> Vec<NaiveDate> {
+        self.group_ends(start, end, |d| d.iso_week())
+    }
+
+    /// trading sessions of trade day in given market, empty if
+    /// date is not a trade day
+    pub fn sessions(&self, date: NaiveDate, market: Market) -> Vec<Session> {
+        if !self.is_trade_day(date) {
+            return Vec::new();
+        }
+        let mut sessions = Vec::new();
+        if let Market::CommodityFutures { night_end: Some(night_end) } = market {
+            if let Some(prev) = self.prev_trade_day(date) {
+                // night session only follows the previous calendar day
+                // or a weekend, not a holiday
+                let gap = (date - prev).num_days();
+                if gap == 1 || (gap == 3 && prev.weekday() == Weekday::Fri) {
+                    let start = prev.and_time(hm(21, 0));
+                    let end = if night_end > hm(21, 0) {
+                        prev.and_time(night_end)
+                    } else {
+                        (prev + Duration::days(1)).and_time(night_end)
+                    };
+                    sessions.push(Session { start, end });
+                }
+            }
+        }
+        for (start, end) in market.day_sessions() {
+            sessions.push(Session {
+                start: date.and_time(start),
+                end: date.and_time(end),
+            });
+        }
+        sessions
+    }
+
+    /// trade day whose sessions contain time, None outside
+    /// trading hours
+    pub fn trade_day_of(&self, time: NaiveDateTime, market: Market) -> Option<NaiveDate> {
+        // trade day on or after the date, or the next one for night sessions
+        let date = time.date();
+        [self.next_trade_day(date - Duration::days(1)), self.next_trade_day(date)]
+            .iter()
+            .flatten()
+            .find(|d| self.sessions(**d, market).iter().any(|s| s.contains(time)))
+            .cloned()
+    }
+
+    /// session a data point at time belongs to, including call
+    /// auctions shortly before the start and late snapshots shortly
+    /// after the end of a session
+    pub fn session_of(&self, time: NaiveDateTime, market: Market) -> Option<Session> {
+        let date = time.date();
+        [self.next_trade_day(date - Duration::days(1)), self.next_trade_day(date)]
+            .iter()
+            .flatten()
+            .flat_map(|d| self.sessions(*d, market))
+            .find(|s| s.start - Duration::minutes(AUCTION_MINUTES) <= time && time <= s.end + Duration::minutes(LATE_MINUTES))
+    }
+
+    /// end time of the bar of given length containing time, as
+    /// labelled by get_price
+    ///
+    /// bars are aligned to the start of each session and never
+    /// span two sessions, so the last bar of a session may be
+    /// shorter. A bar covers (end - interval, end], except that
+    /// the first bar of a session also covers its start and the
+    /// call auction before it.
+    pub fn bar_end(&self, time: NaiveDateTime, market: Market, interval: Duration) -> Option<NaiveDateTime> {
+        let session = self.session_of(time, market)?;
+        let step = interval.num_seconds().max(1);
+        let elapsed = (time - session.start).num_seconds();
+        let n = if elapsed <= 0 { 1 } else { (elapsed + step - 1) / step };
+        Some((session.start + Duration::seconds(n * step)).min(session.end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(s: &str) -> NaiveDate {
+        parse_date(s).unwrap()
+    }
+
+    fn calendar() -> TradingCalendar {
+        // 2019-12-30 Mon .. 2020-01-03 Fri with new year holiday,
+        // then 2020-01-23 before spring festival and 2020-02-03 after
+        TradingCalendar::from_lines(&[
+            "2019-12-27", "2019-12-30", "2019-12-31", "2020-01-02", "2020-01-03", "2020-01-06",
+            "2020-01-23", "2020-02-03",
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_trade_day_arithmetic() {
+        let cal = calendar();
+        assert!(cal.is_trade_day(d("2020-01-02")));
+        assert!(!cal.is_trade_day(d("2020-01-01")));
+        assert_eq!(Some(d("2020-01-02")), cal.next_trade_day(d("2019-12-31")));
+        assert_eq!(Some(d("2019-12-31")), cal.prev_trade_day(d("2020-01-01")));
+        assert_eq!(Some(d("2020-01-06")), cal.offset(d("2020-01-02"), 2));
+        assert_eq!(Some(d("2019-12-30")), cal.offset(d("2020-01-02"), -2));
+        assert_eq!(Some(d("2020-01-02")), cal.offset(d("2020-01-01"), 1));
+        assert_eq!(Some(d("2019-12-31")), cal.offset(d("2020-01-01"), -1));
+        assert_eq!(None, cal.offset(d("2020-01-01"), 0));
+        assert_eq!(None, cal.offset(d("2019-12-27"), -1));
+        assert_eq!(3, cal.count_between(d("2019-12-31"), d("2020-01-05")));
+        assert!(cal.trade_days_between(d("2020-01-07"), d("2020-01-08")).is_empty());
+        assert_eq!(vec![d("2019-12-31"), d("2020-01-23")], cal.month_ends(d("2019-12-01"), d("2020-02-28")));
+        assert_eq!(
+            vec![d("2019-12-27"), d("2020-01-03"), d("2020-01-06"), d("2020-01-23")],
+            cal.week_ends(d("2019-12-01"), d("2020-02-28"))
+        );
+    }
+
+    #[test]
+    fn test_sessions() {
+        let cal = calendar();
+        let market = Market::CommodityFutures { night_end: Some(hm(1, 0)) };
+        let sessions = cal.sessions(d("2020-01-06"), market);
+        assert_eq!(4, sessions.len());
+        assert_eq!(d("2020-01-03").and_time(hm(21, 0)), sessions[0].start);
+        assert_eq!(d("2020-01-04").and_time(hm(1, 0)), sessions[0].end);
+        // no night session after spring festival
+        assert_eq!(3, cal.sessions(d("2020-02-03"), market).len());
+        assert_eq!(2, cal.sessions(d("2020-01-06"), Market::Stock).len());
+        assert!(cal.sessions(d("2020-01-04"), Market::Stock).is_empty());
+
+        assert_eq!(
+            Some(d("2020-01-06")),
+            cal.trade_day_of(d("2020-01-04").and_time(hm(0, 30)), market)
+        );
+        assert_eq!(
+            Some(d("2020-01-03")),
+            cal.trade_day_of(d("2020-01-03").and_time(hm(14, 0)), Market::Stock)
+        );
+        assert_eq!(None, cal.trade_day_of(d("2020-01-03").and_time(hm(12, 0)), Market::Stock));
+    }
+
+    #[test]
+    fn test_no_night_session_after_holiday() {
+        let market = Market::CommodityFutures { night_end: Some(hm(23, 0)) };
+        let nights = |cal: &TradingCalendar, date: &str| {
+            cal.sessions(d(date), market).iter().filter(|s| s.start.time() == hm(21, 0)).count()
+        };
+        // new year's day on wednesday
+        let cal = calendar();
+        assert_eq!(1, nights(&cal, "2019-12-31"));
+        assert_eq!(0, nights(&cal, "2020-01-02"));
+        assert_eq!(1, nights(&cal, "2020-01-03"));
+        assert_eq!(0, nights(&cal, "2020-02-03"));
+        // national day, 2019-10-08 is a tuesday
+        let cal = TradingCalendar::from_lines(&["2019-09-27", "2019-09-30", "2019-10-08", "2019-10-09"]).unwrap();
+        assert_eq!(1, nights(&cal, "2019-09-30"));
+        assert_eq!(0, nights(&cal, "2019-10-08"));
+        assert_eq!(1, nights(&cal, "2019-10-09"));
+    }
+
+    #[test]
+    fn test_bar_end() {
+        let cal = calendar();
+        let at = |date: &str, h: u32, m: u32, s: u32| d(date).and_hms_opt(h, m, s).unwrap();
+        let minute = Duration::minutes(1);
+        let stock = Market::Stock;
+        // call auction and session start go to the first bar
+        assert_eq!(Some(at("2020-01-03", 9, 31, 0)), cal.bar_end(at("2020-01-03", 9, 25, 3), stock, minute));
+        assert_eq!(Some(at("2020-01-03", 9, 31, 0)), cal.bar_end(at("2020-01-03", 9, 30, 0), stock, minute));
+        assert_eq!(Some(at("2020-01-03", 9, 31, 0)), cal.bar_end(at("2020-01-03", 9, 31, 0), stock, minute));
+        assert_eq!(Some(at("2020-01-03", 9, 32, 0)), cal.bar_end(at("2020-01-03", 9, 31, 1), stock, minute));
+        assert_eq!(Some(at("2020-01-03", 11, 30, 0)), cal.bar_end(at("2020-01-03", 11, 30, 2), stock, minute));
+        assert_eq!(None, cal.bar_end(at("2020-01-03", 12, 0, 0), stock, minute));
+        // 45 minute bars end with a partial bar at the lunch break
+        let bars = Duration::minutes(45);
+        assert_eq!(Some(at("2020-01-03", 11, 0, 0)), cal.bar_end(at("2020-01-03", 10, 30, 0), stock, bars));
+        assert_eq!(Some(at("2020-01-03", 11, 30, 0)), cal.bar_end(at("2020-01-03", 11, 10, 0), stock, bars));
+        assert_eq!(Some(at("2020-01-03", 13, 45, 0)), cal.bar_end(at("2020-01-03", 13, 0, 5), stock, bars));
+        // night session of friday
+        let market = Market::CommodityFutures { night_end: Some(hm(23, 0)) };
+        assert_eq!(Some(at("2020-01-03", 21, 1, 0)), cal.bar_end(at("2020-01-03", 20, 59, 0), market, minute));
+        assert_eq!(Some(at("2020-01-03", 10, 15, 0)), cal.bar_end(at("2020-01-03", 10, 15, 1), market, minute));
+        assert_eq!(Some(at("2020-01-03", 10, 31, 0)), cal.bar_end(at("2020-01-03", 10, 29, 0), market, minute));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("calendar.txt");
+        calendar().save(&path).unwrap();
+        assert_eq!(calendar(), TradingCalendar::load(&path).unwrap());
+    }
+}
@@ -0,0 +1,221 @@
+//! Cassette
+//!
+//! Record-and-replay transport. In record mode every request body
+//! and the raw response body of the wrapped transport are appended
+//! to a cassette file, one JSON object per line. In replay mode the
+//! responses are served from the cassette without network, requests
+//! not found in the cassette are rejected.
+//!
+//! Credentials are never written to the cassette: the token is removed
+//! from requests, and mob, pwd and the returned token are redacted
+//! for token requests.
+use crate::{Error, Result, Transport};
+use futures::future::BoxFuture;
+use serde_derive::*;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// token served in replay mode
+pub const CASSETTE_TOKEN: &str = "cassette-token";
+
+const TOKEN_METHODS: &[&str] = &["get_token", "get_current_token"];
+
+/// one recorded request and response
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CassetteEntry {
+    pub request: Value,
+    pub response: String,
+}
+
+enum Mode {
+    Record {
+        inner: Arc<dyn Transport>,
+        file: Mutex<File>,
+    },
+    Replay {
+        entries: Mutex<Vec<(Value, VecDeque<String>)>>,
+    },
+}
+
+/// CassetteTransport
+///
+/// transport recording to or replaying from a cassette file
+pub struct CassetteTransport {
+    mode: Mode,
+}
+
+impl CassetteTransport {
+    /// record requests sent through inner transport to the
+    /// cassette file, existing file is truncated
+    pub fn record<T, P>(inner: T, path: P) -> Result<Self>
+    where
+        T: Transport + 'static,
+        P: AsRef<Path>,
+    {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(CassetteTransport {
+            mode: Mode::Record {
+                inner: Arc::new(inner),
+                file: Mutex::new(file),
+            },
+        })
+    }
+
+    /// replay responses from the cassette file.
+    /// identical requests are answered in recorded order,
+    /// the last response is repeated once all are served
+    pub fn replay<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut entries: Vec<(Value, VecDeque<String>)> = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: CassetteEntry = serde_json::from_str(&line)?;
+            match entries.iter_mut().find(|(req, _)| *req == entry.request) {
+                Some((_, responses)) => responses.push_back(entry.response),
+                None => entries.push((entry.request, VecDeque::from(vec![entry.response]))),
+            }
+        }
+        Ok(CassetteTransport {
+            mode: Mode::Replay {
+                entries: Mutex::new(entries),
+            },
+        })
+    }
+
+    fn replay_response(entries: &Mutex<Vec<(Value, VecDeque<String>)>>, request: &Value) -> Result<Vec<u8>> {
+        let mut entries = entries.lock().unwrap();
+        let responses = entries
+            .iter_mut()
+            .find(|(req, _)| req == request)
+            .map(|(_, responses)| responses)
+            .ok_or_else(|| Error::Client(format!("no cassette entry matches request {}", request)))?;
+        let response = if responses.len() > 1 {
+            responses.pop_front().unwrap()
+        } else {
+            responses.front().cloned().unwrap()
+        };
+        Ok(response.into_bytes())
+    }
+}
+
+impl Transport for CassetteTransport {
+    fn send(&self, body: String) -> BoxFuture<'_, Result<Vec<u8>>> {
+        Box::pin(async move {
+            let request = redact_request(&body)?;
+            match self.mode {
+                Mode::Record { ref inner, ref file } => {
+                    let method = entry_method(&body);
+                    let response = inner.send(body).await?;
+                    let entry = CassetteEntry {
+                        request,
+                        response: redact_response(&method, &response)?,
+                    };
+                    let mut line = serde_json::to_string(&entry)?;
+                    line.push('\n');
+                    let mut file = file.lock().unwrap();
+                    file.write_all(line.as_bytes())?;
+                    file.flush()?;
+                    Ok(response)
+                }
+                Mode::Replay { ref entries } => Self::replay_response(entries, &request),
+            }
+        })
+    }
+}
+
+fn entry_method(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| v.get("method").and_then(Value::as_str).map(str::to_owned))
+        .unwrap_or_default()
+}
+
+/// remove credentials from request body
+pub fn redact_request(body: &str) -> Result<Value> {
+    let mut request: Value = serde_json::from_str(body)?;
+    let map = request
+        .as_object_mut()
+        .ok_or_else(|| Error::Client(format!("request body is not an object: {}", body)))?;
+    map.remove("token");
+    let is_token_method = map
+        .get("method")
+        .and_then(Value::as_str)
+        .map(|m| TOKEN_METHODS.contains(&m))
+        .unwrap_or(false);
+    if is_token_method {
+        map.remove("mob");
+        map.remove("pwd");
+    }
+    Ok(request)
+}
+
+fn redact_response(method: &str, response: &[u8]) -> Result<String> {
+    let response = String::from_utf8(response.to_vec())?;
+    if TOKEN_METHODS.contains(&method) && !response.starts_with("error") {
+        return Ok(CASSETTE_TOKEN.to_owned());
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FakeResponse, FakeTransport, GetIndexStocks, JqdataClient};
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let command = || GetIndexStocks {
+            code: "000300.XSHG".to_owned(),
+            date: "2020-01-02".to_owned(),
+        };
+        block_on(async {
+            let fake = FakeTransport::new();
+            fake.respond_any("get_current_token", FakeResponse::body("secret-token"))
+                .respond_any("get_index_stocks", FakeResponse::lines(&["000001.XSHE", "600000.XSHG"]));
+            let recorder = CassetteTransport::record(fake, &path).unwrap();
+            let client = JqdataClient::with_transport(recorder, "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            client.execute(command()).await.unwrap();
+        });
+
+        let cassette = std::fs::read_to_string(&path).unwrap();
+        assert!(!cassette.contains("secret-token"));
+        assert!(!cassette.contains("pass"));
+
+        block_on(async {
+            let player = CassetteTransport::replay(&path).unwrap();
+            let client = JqdataClient::with_transport(player, "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            let stocks = client.execute(command()).await.unwrap();
+            assert_eq!(vec!["000001.XSHE".to_owned(), "600000.XSHG".to_owned()], stocks);
+            let unmatched = client
+                .execute(GetIndexStocks {
+                    code: "000905.XSHG".to_owned(),
+                    date: "2020-01-02".to_owned(),
+                })
+                .await;
+            assert!(unmatched.is_err());
+        });
+        assert_eq!(
+            json!({"method": "get_index_stocks", "code": "000300.XSHG", "date": "2020-01-02"}),
+            redact_request(r#"{"token":"t","method":"get_index_stocks","code":"000300.XSHG","date":"2020-01-02"}"#).unwrap()
+        );
+    }
+}
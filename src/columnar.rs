@@ -0,0 +1,357 @@
+//! Columnar export
+//!
+//! Converts csv responses into Arrow `RecordBatch` and writes
+//! them as Parquet files, enabled by feature "arrow".
+//!
+//! Typed responses have the fixed schema of their row type, so
+//! chunks of one request type can be concatenated: `Number` and
+//! float fields are Float64, integers Int64 and Option fields are
+//! nullable. String fields are Utf8, except dates: `date`, which
+//! also holds the time of minute bars, is Timestamp(Second), `time`
+//! of ticks Timestamp(Millisecond), and fields named `day` or ending
+//! with `_date` or `_day` are Date32. Columns not declared by the
+//! row type are dropped, missing nullable columns are nulls and
+//! values that cannot be parsed are errors.
+//!
+//! Column types of raw responses are inferred from values:
+//! "%Y-%m-%d" dates as Date32, "%Y-%m-%d %H:%M:%S" times as
+//! Timestamp(Second), integers as Int64, decimals as Float64 and
+//! others as Utf8. Empty values are nulls, and values with leading
+//! zeros such as "000001" are kept as strings.
+use crate::raw::RawCommand;
+use crate::{struct_field_types, CsvListBodyConsumer, CsvRow, Error, FieldKind, HasMethod, JqdataClient, Result};
+use arrow::array::{
+    ArrayRef, BooleanArray, Date32Array, Float64Array, Int64Array, StringArray, TimestampMillisecondArray,
+    TimestampSecondArray,
+};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use chrono::{NaiveDate, NaiveDateTime};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// formats of typed date and time fields besides DATE_FORMAT
+const TIME_FORMATS: &[&str] = &[DATETIME_FORMAT, "%Y-%m-%d %H:%M:%S%.f", "%Y%m%d%H%M%S%.f", "%Y%m%d%H%M%S"];
+
+fn arrow_error<E: std::fmt::Display>(e: E) -> Error {
+    Error::Client(format!("arrow error: {}", e))
+}
+
+fn epoch() -> NaiveDate {
+    NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()
+}
+
+fn is_integer(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    !digits.is_empty()
+        && digits.bytes().all(|b| b.is_ascii_digit())
+        && (digits.len() == 1 || !digits.starts_with('0'))
+        && s.parse::<i64>().is_ok()
+}
+
+fn is_float(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    // keep codes like "000001" as strings
+    if digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.") {
+        return false;
+    }
+    s.parse::<f64>().map(|f| f.is_finite()).unwrap_or(false)
+}
+
+/// infer arrow type of column from its non-empty values
+fn infer_type(values: &[Option<&str>]) -> DataType {
+    let present: Vec<&str> = values.iter().filter_map(|v| *v).collect();
+    if present.is_empty() {
+        return DataType::Utf8;
+    }
+    if present.iter().all(|v| NaiveDate::parse_from_str(v, DATE_FORMAT).is_ok()) {
+        DataType::Date32
+    } else if present.iter().all(|v| NaiveDateTime::parse_from_str(v, DATETIME_FORMAT).is_ok()) {
+        DataType::Timestamp(TimeUnit::Second, None)
+    } else if present.iter().all(|v| is_integer(v)) {
+        DataType::Int64
+    } else if present.iter().all(|v| is_float(v)) {
+        DataType::Float64
+    } else {
+        DataType::Utf8
+    }
+}
+
+/// date or time, dates are at midnight
+fn parse_datetime(s: &str) -> Option<NaiveDateTime> {
+    TIME_FORMATS
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+        .or_else(|| NaiveDate::parse_from_str(s, DATE_FORMAT).ok().map(|d| d.and_hms_opt(0, 0, 0).unwrap()))
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.to_lowercase().as_str() {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// parse present values of column, the first invalid one is an error
+fn parse_values<T, F>(name: &str, ty: &DataType, values: &[Option<&str>], parse: F) -> Result<Vec<Option<T>>>
+where
+    F: Fn(&str) -> Option<T>,
+{
+    values
+        .iter()
+        .map(|v| match v {
+            Some(s) => parse(s)
+                .map(Some)
+                .ok_or_else(|| Error::Client(format!("invalid {} value {} in column {}", ty, s, name))),
+            None => Ok(None),
+        })
+        .collect()
+}
+
+fn build_array(name: &str, ty: &DataType, values: &[Option<&str>]) -> Result<ArrayRef> {
+    Ok(match ty {
+        DataType::Date32 => Arc::new(Date32Array::from(parse_values(name, ty, values, |s| {
+            parse_datetime(s).map(|t| (t.date() - epoch()).num_days() as i32)
+        })?)),
+        DataType::Timestamp(TimeUnit::Millisecond, _) => Arc::new(TimestampMillisecondArray::from(parse_values(
+            name,
+            ty,
+            values,
+            |s| parse_datetime(s).map(|t| t.and_utc().timestamp_millis()),
+        )?)),
+        DataType::Timestamp(..) => Arc::new(TimestampSecondArray::from(parse_values(name, ty, values, |s| {
+            parse_datetime(s).map(|t| t.and_utc().timestamp())
+        })?)),
+        DataType::Int64 => Arc::new(Int64Array::from(parse_values(name, ty, values, |s| s.parse().ok())?)),
+        DataType::Float64 => Arc::new(Float64Array::from(parse_values(name, ty, values, |s| s.parse().ok())?)),
+        DataType::Boolean => Arc::new(BooleanArray::from(parse_values(name, ty, values, parse_bool)?)),
+        _ => Arc::new(StringArray::from(values.to_vec())),
+    })
+}
+
+/// arrow type of string field by its name
+fn string_type(name: &str) -> DataType {
+    if name == "date" {
+        DataType::Timestamp(TimeUnit::Second, None)
+    } else if name == "time" {
+        DataType::Timestamp(TimeUnit::Millisecond, None)
+    } else if name == "day" || name.ends_with("_date") || name.ends_with("_day") {
+        DataType::Date32
+    } else {
+        DataType::Utf8
+    }
+}
+
+fn read_csv(body: &[u8]) -> Result<(csv::StringRecord, Vec<csv::StringRecord>)> {
+    let mut reader = csv::ReaderBuilder::new().from_reader(body);
+    let headers = reader.headers()?.clone();
+    if let Some(first_col) = headers.get(0) {
+        if first_col.starts_with("error") {
+            return Err(Error::Server(first_col.to_owned()));
+        }
+    }
+    let records = reader.records().collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok((headers, records))
+}
+
+fn column_values(records: &[csv::StringRecord], idx: usize) -> Vec<Option<&str>> {
+    records.iter().map(|r| r.get(idx).filter(|s| !s.is_empty())).collect()
+}
+
+/// schema of row type from its declared fields
+pub fn schema_of<T: CsvRow>() -> Schema {
+    let fields: Vec<Field> = struct_field_types::<T>()
+        .into_iter()
+        .map(|f| {
+            let ty = match f.kind {
+                FieldKind::Bool => DataType::Boolean,
+                FieldKind::Integer => DataType::Int64,
+                FieldKind::Float | FieldKind::Decimal => DataType::Float64,
+                FieldKind::String => string_type(f.name),
+                FieldKind::Other => DataType::Utf8,
+            };
+            Field::new(f.name, ty, f.nullable)
+        })
+        .collect();
+    Schema::new(fields)
+}
+
+/// convert csv body with header into record batch, inferring
+/// column types from values
+pub fn csv_to_record_batch(body: &[u8]) -> Result<RecordBatch> {
+    let (headers, records) = read_csv(body)?;
+    let mut fields = Vec::with_capacity(headers.len());
+    let mut columns = Vec::with_capacity(headers.len());
+    for (idx, name) in headers.iter().enumerate() {
+        let values = column_values(&records, idx);
+        let ty = infer_type(&values);
+        columns.push(build_array(name, &ty, &values)?);
+        fields.push(Field::new(name, ty, true));
+    }
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).map_err(arrow_error)
+}
+
+/// convert csv body with header into record batch of schema,
+/// see module docs for undeclared and missing columns
+pub fn csv_to_record_batch_with_schema(body: &[u8], schema: &Schema) -> Result<RecordBatch> {
+    let (headers, records) = read_csv(body)?;
+    let mut columns = Vec::with_capacity(schema.fields().len());
+    for field in schema.fields() {
+        let values = match headers.iter().position(|h| h == field.name()) {
+            Some(idx) => column_values(&records, idx),
+            None if field.is_nullable() => vec![None; records.len()],
+            None => return Err(Error::Client(format!("missing column {}", field.name()))),
+        };
+        columns.push(build_array(field.name(), field.data_type(), &values)?);
+    }
+    RecordBatch::try_new(Arc::new(schema.clone()), columns).map_err(arrow_error)
+}
+
+/// convert deserialized rows into record batch of the schema
+/// of their type
+pub fn rows_to_record_batch<T: CsvRow + Serialize>(rows: &[T]) -> Result<RecordBatch> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    let body = writer.into_inner().map_err(|e| Error::Client(e.to_string()))?;
+    let schema = schema_of::<T>();
+    if rows.is_empty() {
+        return Ok(RecordBatch::new_empty(Arc::new(schema)));
+    }
+    csv_to_record_batch_with_schema(&body, &schema)
+}
+
+/// write record batch in parquet format with snappy compression
+pub fn write_parquet<W: Write + Send>(batch: &RecordBatch, writer: W) -> Result<()> {
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(writer, batch.schema(), Some(props)).map_err(arrow_error)?;
+    writer.write(batch).map_err(arrow_error)?;
+    writer.close().map_err(arrow_error)?;
+    Ok(())
+}
+
+/// write record batch to parquet file
+pub fn write_parquet_file<P: AsRef<Path>>(batch: &RecordBatch, path: P) -> Result<()> {
+    let file = std::fs::File::create(path)?;
+    write_parquet(batch, file)
+}
+
+impl JqdataClient {
+    /// Execute request of csv format and return the
+    /// response as record batch with the schema of its row type
+    pub async fn execute_arrow<C>(&self, command: C) -> Result<RecordBatch>
+    where
+        C: HasMethod + CsvListBodyConsumer + Serialize,
+    {
+        let body = self.send_command(command).await?;
+        csv_to_record_batch_with_schema(&body, &schema_of::<C::Output>())
+    }
+
+    /// Execute method with json object params and return
+    /// the csv response as record batch of inferred types
+    pub async fn execute_raw_arrow(&self, method: &str, params: Value) -> Result<RecordBatch> {
+        let body = self.send_command(RawCommand::new(method, params)?).await?;
+        csv_to_record_batch(&body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Numeric, Price, Security, Tick};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    const PRICES: &str = "date,code,open,volume,paused,time\n\
+        2020-01-02,000001.XSHE,16.65,153023187,0,2020-01-02 09:31:00\n\
+        2020-01-03,000001.XSHE,16.94,111619481,,2020-01-02 09:32:00\n";
+
+    #[test]
+    fn test_csv_to_record_batch() {
+        let batch = csv_to_record_batch(PRICES.as_bytes()).unwrap();
+        let types: Vec<DataType> = batch.schema().fields().iter().map(|f| f.data_type().clone()).collect();
+        assert_eq!(
+            vec![
+                DataType::Date32,
+                DataType::Utf8,
+                DataType::Float64,
+                DataType::Int64,
+                DataType::Int64,
+                DataType::Timestamp(TimeUnit::Second, None),
+            ],
+            types
+        );
+        assert_eq!(2, batch.num_rows());
+        assert_eq!(1, batch.column(4).null_count());
+        assert!(csv_to_record_batch(b"error: invalid token").is_err());
+    }
+
+    #[test]
+    fn test_fixed_schema_of_row_type() {
+        let schema = schema_of::<Price>();
+        let field = |name: &str| schema.field_with_name(name).unwrap().clone();
+        let seconds = DataType::Timestamp(TimeUnit::Second, None);
+        assert_eq!((&seconds, false), (field("date").data_type(), field("date").is_nullable()));
+        assert_eq!((&DataType::Float64, false), (field("open").data_type(), field("open").is_nullable()));
+        assert_eq!((&DataType::Int64, true), (field("paused").data_type(), field("paused").is_nullable()));
+        // chunks with different columns and values share the schema
+        let first = "date,open,close,high,low,volume,money,paused\n2020-01-02,16,16.87,16.95,16.55,153023187,2571196482.6,0\n";
+        let second = "date,open,close,high,low,volume,money,factor\n2020-01-03 09:31:00,16.94,17.0,17.1,16.8,111619481,1.9e9,1.0\n";
+        let first = csv_to_record_batch_with_schema(first.as_bytes(), &schema).unwrap();
+        let second = csv_to_record_batch_with_schema(second.as_bytes(), &schema).unwrap();
+        assert_eq!(first.schema(), second.schema());
+        assert_eq!(1, second.column_by_name("paused").unwrap().null_count());
+        assert!(csv_to_record_batch_with_schema(b"date,open\n2020-01-02,16\n", &schema).is_err());
+        // unparsable values are errors instead of nulls
+        let invalid = "date,open,close,high,low,volume,money\n2020-01-02,16.6,n/a,16.95,16.55,1,1\n";
+        assert!(csv_to_record_batch_with_schema(invalid.as_bytes(), &schema).is_err());
+        let invalid = "date,open,close,high,low,volume,money\n2020-13-02,16.6,16.8,16.95,16.55,1,1\n";
+        assert!(csv_to_record_batch_with_schema(invalid.as_bytes(), &schema).is_err());
+        let millis = DataType::Timestamp(TimeUnit::Millisecond, None);
+        assert_eq!(&millis, schema_of::<Tick>().field_with_name("time").unwrap().data_type());
+        assert_eq!(&DataType::Date32, schema_of::<Security>().field_with_name("start_date").unwrap().data_type());
+        assert_eq!(&DataType::Utf8, schema_of::<Security>().field_with_name("code").unwrap().data_type());
+        let rows = vec![Price {
+            date: "2020-01-02".to_owned(),
+            open: Numeric::from_f64(16.65),
+            close: Numeric::from_f64(16.87),
+            high: Numeric::from_f64(16.95),
+            low: Numeric::from_f64(16.55),
+            volume: Numeric::from_f64(100.0),
+            money: Numeric::from_f64(1665.0),
+            paused: None,
+            high_limit: None,
+            low_limit: None,
+            avg: None,
+            pre_close: None,
+            open_interest: None,
+            extra: Default::default(),
+        }];
+        assert_eq!(first.schema(), rows_to_record_batch(&rows).unwrap().schema());
+        assert_eq!(Arc::new(schema), rows_to_record_batch::<Price>(&[]).unwrap().schema());
+    }
+
+    #[test]
+    fn test_write_parquet() {
+        let batch = csv_to_record_batch(PRICES.as_bytes()).unwrap();
+        let mut buf = Vec::new();
+        write_parquet(&batch, &mut buf).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(buf))
+            .unwrap()
+            .build()
+            .unwrap();
+        let rows: usize = reader.map(|b| b.unwrap().num_rows()).sum();
+        assert_eq!(2, rows);
+    }
+}
@@ -0,0 +1,355 @@
+//! Continuous futures
+//!
+//! Stitches daily bars of dominant contracts into one continuous
+//! series of a product, with a log of every roll.
+//!
+//! The dominant contract is sampled with get_dominant_future every
+//! few trade days, and the trade days between two samples with
+//! different contracts are bisected to find the first day of the
+//! new contract, as `IndexHistoryBuilder` does for constituents. A
+//! roll happens on the first day a new contract is dominant, and
+//! a dominance that starts and ends between two samples is missed.
+//! The price gap of a roll is measured on the
+//! last day before the roll, where both contracts have a close.
+//! Back adjustment shifts (difference) or scales (ratio) all bars
+//! before the roll, so the latest prices are unchanged.
+use crate::{GetDominantFuture, GetPricePeriod, GetTradeDays, JqdataClient, Numeric, Price, Result};
+use serde_derive::*;
+use std::collections::{BTreeMap, HashSet};
+
+/// price adjustment at rolls
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Adjustment {
+    /// raw prices of dominant contracts
+    None,
+    /// add the close difference of new and old contract
+    /// to earlier bars
+    Difference,
+    /// multiply earlier bars by the close ratio of new
+    /// and old contract
+    Ratio,
+}
+
+/// daily bar of continuous series
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContinuousBar {
+    pub date: String,
+    /// dominant contract of the day
+    pub contract: String,
+    pub open: f64,
+    pub close: f64,
+    pub high: f64,
+    pub low: f64,
+    pub volume: f64,
+    pub money: f64,
+    pub open_interest: Option<f64>,
+}
+
+/// switch of dominant contract
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Roll {
+    /// first trade day of the new contract
+    pub date: String,
+    pub from: String,
+    pub to: String,
+    /// closes of both contracts on the day before the roll,
+    /// None if the contract has no bar on that day
+    pub old_close: Option<f64>,
+    pub new_close: Option<f64>,
+    /// difference or ratio applied to earlier bars,
+    /// 0 or 1 if not adjusted
+    pub adjustment: f64,
+}
+
+/// ContinuousSeries
+///
+/// bars and rolls of one product, in date order
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContinuousSeries {
+    pub product: String,
+    pub bars: Vec<ContinuousBar>,
+    pub rolls: Vec<Roll>,
+}
+
+/// ContinuousFutures
+///
+/// builder of continuous series with given adjustment,
+/// dominant contracts are sampled every 10 trade days by default
+#[derive(Clone)]
+pub struct ContinuousFutures {
+    client: JqdataClient,
+    adjustment: Adjustment,
+    every: usize,
+}
+
+impl ContinuousFutures {
+    /// no adjustment by default
+    pub fn new(client: JqdataClient) -> Self {
+        ContinuousFutures {
+            client,
+            adjustment: Adjustment::None,
+            every: 10,
+        }
+    }
+
+    pub fn adjustment(mut self, adjustment: Adjustment) -> Self {
+        self.adjustment = adjustment;
+        self
+    }
+
+    /// trade days between samples, a contract dominant for fewer
+    /// trade days may be missed
+    pub fn every(mut self, every: usize) -> Self {
+        self.every = every.max(1);
+        self
+    }
+
+    async fn dominant(&self, product: &str, date: &str) -> Result<Option<String>> {
+        let lines = self
+            .client
+            .execute(GetDominantFuture {
+                code: product.to_owned(),
+                date: date.to_owned(),
+            })
+            .await?;
+        Ok(lines.into_iter().map(|l| l.trim().to_owned()).find(|l| !l.is_empty()))
+    }
+
+    /// dominant contract of each trade day between start and end,
+    /// days without dominant contract are skipped
+    pub async fn dominant_contracts(&self, product: &str, start_date: &str, end_date: &str) -> Result<Vec<(String, String)>> {
+        let trade_days = self
+            .client
+            .execute(GetTradeDays {
+                date: start_date.to_owned(),
+                end_date: Some(end_date.to_owned()),
+            })
+            .await?;
+        let mut samples: Vec<usize> = (0..trade_days.len()).step_by(self.every).collect();
+        if !trade_days.is_empty() && samples.last() != Some(&(trade_days.len() - 1)) {
+            samples.push(trade_days.len() - 1);
+        }
+        // dominant contracts by index of trade day
+        let mut fetched: BTreeMap<usize, Option<String>> = BTreeMap::new();
+        for &i in &samples {
+            let contract = self.dominant(product, &trade_days[i]).await?;
+            fetched.insert(i, contract);
+        }
+        let mut pending: Vec<(usize, usize)> = samples.windows(2).map(|w| (w[0], w[1])).collect();
+        while let Some((lo, hi)) = pending.pop() {
+            if hi - lo < 2 || fetched[&lo] == fetched[&hi] {
+                continue;
+            }
+            let mid = lo + (hi - lo) / 2;
+            let contract = self.dominant(product, &trade_days[mid]).await?;
+            fetched.insert(mid, contract);
+            pending.push((lo, mid));
+            pending.push((mid, hi));
+        }
+        // each day has the contract of the latest fetched day
+        let mut dominants = Vec::with_capacity(trade_days.len());
+        for (i, day) in trade_days.into_iter().enumerate() {
+            if let Some((_, Some(contract))) = fetched.range(..=i).next_back() {
+                dominants.push((day, contract.clone()));
+            }
+        }
+        Ok(dominants)
+    }
+
+    /// build continuous daily series of product, such as "AG"
+    pub async fn build(&self, product: &str, start_date: &str, end_date: &str) -> Result<ContinuousSeries> {
+        let dominants = self.dominant_contracts(product, start_date, end_date).await?;
+        // segments of consecutive days with the same contract
+        let mut segments: Vec<(String, Vec<String>)> = Vec::new();
+        for (day, contract) in dominants {
+            match segments.last_mut() {
+                Some((c, days)) if *c == contract => days.push(day),
+                _ => segments.push((contract, vec![day])),
+            }
+        }
+        let mut bars: Vec<ContinuousBar> = Vec::new();
+        let mut rolls = Vec::new();
+        let mut prev: Option<(String, String)> = None;
+        for (contract, days) in segments {
+            // the day before the roll is fetched to measure the gap
+            let first = prev.as_ref().map(|(_, d)| d.clone()).unwrap_or_else(|| days[0].clone());
+            let prices = self
+                .client
+                .execute(GetPricePeriod {
+                    code: contract.clone(),
+                    unit: "1d".to_owned(),
+                    date: first,
+                    end_date: days[days.len() - 1].clone(),
+                    fq_ref_date: None,
+                })
+                .await?;
+            if let Some((old_contract, prev_day)) = prev.take() {
+                let old_close = bars.last().filter(|b| day_of(&b.date) == prev_day).map(|b| b.close);
+                let new_close = prices.iter().find(|p| day_of(&p.date) == prev_day).map(|p| p.close.to_f64());
+                let adjustment = match (self.adjustment, old_close, new_close) {
+                    (Adjustment::Difference, Some(o), Some(n)) => n - o,
+                    (Adjustment::Ratio, Some(o), Some(n)) if o != 0.0 => n / o,
+                    (Adjustment::Ratio, ..) => 1.0,
+                    _ => 0.0,
+                };
+                adjust(&mut bars, self.adjustment, adjustment);
+                rolls.push(Roll {
+                    date: days[0].clone(),
+                    from: old_contract,
+                    to: contract.clone(),
+                    old_close,
+                    new_close,
+                    adjustment,
+                });
+            }
+            let segment: HashSet<&str> = days.iter().map(String::as_str).collect();
+            for p in prices.iter().filter(|p| segment.contains(day_of(&p.date))) {
+                bars.push(to_bar(&contract, p));
+            }
+            prev = Some((contract, days[days.len() - 1].clone()));
+        }
+        Ok(ContinuousSeries {
+            product: product.to_owned(),
+            bars,
+            rolls,
+        })
+    }
+}
+
+fn day_of(date: &str) -> &str {
+    date.get(..10).unwrap_or(date)
+}
+
+fn to_bar(contract: &str, p: &Price) -> ContinuousBar {
+    ContinuousBar {
+        date: p.date.clone(),
+        contract: contract.to_owned(),
+        open: p.open.to_f64(),
+        close: p.close.to_f64(),
+        high: p.high.to_f64(),
+        low: p.low.to_f64(),
+        volume: p.volume.to_f64(),
+        money: p.money.to_f64(),
+        open_interest: p.open_interest.as_ref().map(Numeric::to_f64),
+    }
+}
+
+/// apply back adjustment of one roll to earlier bars
+fn adjust(bars: &mut [ContinuousBar], adjustment: Adjustment, value: f64) {
+    for b in bars.iter_mut() {
+        match adjustment {
+            Adjustment::None => (),
+            Adjustment::Difference => {
+                b.open += value;
+                b.close += value;
+                b.high += value;
+                b.low += value;
+            }
+            Adjustment::Ratio => {
+                b.open *= value;
+                b.close *= value;
+                b.high *= value;
+                b.low *= value;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FakeResponse, FakeTransport};
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    const HEADER: &[&str] = &["date", "open", "close", "high", "low", "volume", "money"];
+
+    fn series(adjustment: Adjustment) -> ContinuousSeries {
+        block_on(async {
+            let fake = FakeTransport::new();
+            fake.respond(
+                "get_trade_days",
+                json!({}),
+                FakeResponse::lines(&["2020-01-02", "2020-01-03", "2020-01-06"]),
+            )
+            .respond("get_dominant_future", json!({"date": "2020-01-06"}), FakeResponse::lines(&["AG2006.XSGE"]))
+            .respond("get_dominant_future", json!({}), FakeResponse::lines(&["AG2002.XSGE"]))
+            .respond(
+                "get_price_period",
+                json!({"code": "AG2002.XSGE"}),
+                FakeResponse::csv(
+                    HEADER,
+                    &[
+                        &["2020-01-02", "100", "100", "101", "99", "10", "1000"],
+                        &["2020-01-03", "100", "110", "111", "99", "10", "1000"],
+                    ],
+                ),
+            )
+            .respond(
+                "get_price_period",
+                json!({"code": "AG2006.XSGE", "date": "2020-01-03"}),
+                FakeResponse::csv(
+                    HEADER,
+                    &[
+                        &["2020-01-03", "120", "121", "122", "119", "5", "600"],
+                        &["2020-01-06", "121", "125", "126", "120", "20", "2500"],
+                    ],
+                ),
+            );
+            let client = JqdataClient::with_transport(fake, "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            ContinuousFutures::new(client)
+                .adjustment(adjustment)
+                .build("AG", "2020-01-02", "2020-01-06")
+                .await
+                .unwrap()
+        })
+    }
+
+    #[test]
+    fn test_continuous_without_adjustment() {
+        let s = series(Adjustment::None);
+        assert_eq!(vec![100.0, 110.0, 125.0], s.bars.iter().map(|b| b.close).collect::<Vec<_>>());
+        assert_eq!("AG2006.XSGE", s.bars[2].contract);
+        assert_eq!(1, s.rolls.len());
+        assert_eq!("2020-01-06", s.rolls[0].date);
+        assert_eq!((Some(110.0), Some(121.0)), (s.rolls[0].old_close, s.rolls[0].new_close));
+    }
+
+    #[test]
+    fn test_continuous_back_adjustment() {
+        let s = series(Adjustment::Difference);
+        assert_eq!(vec![111.0, 121.0, 125.0], s.bars.iter().map(|b| b.close).collect::<Vec<_>>());
+        assert_eq!(11.0, s.rolls[0].adjustment);
+        let s = series(Adjustment::Ratio);
+        assert!((s.bars[1].close - 121.0).abs() < 1e-9);
+        assert!((s.bars[0].close - 110.0).abs() < 1e-9);
+        assert_eq!(125.0, s.bars[2].close);
+    }
+
+    #[test]
+    fn test_roll_date_is_bisected() {
+        block_on(async {
+            let days: Vec<String> = (1..=30).map(|d| format!("2020-03-{:02}", d)).collect();
+            let day_refs: Vec<&str> = days.iter().map(String::as_str).collect();
+            let fake = FakeTransport::new();
+            fake.respond("get_trade_days", json!({}), FakeResponse::lines(&day_refs));
+            for (i, day) in days.iter().enumerate() {
+                let contract = if i < 17 { "AG2006.XSGE" } else { "AG2012.XSGE" };
+                fake.respond("get_dominant_future", json!({ "date": day }), FakeResponse::lines(&[contract]));
+            }
+            let client = JqdataClient::with_transport(fake.clone(), "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            let dominants = ContinuousFutures::new(client)
+                .dominant_contracts("AG", "2020-03-01", "2020-03-30")
+                .await
+                .unwrap();
+            assert_eq!(30, dominants.len());
+            assert_eq!(("2020-03-17".to_owned(), "AG2006.XSGE".to_owned()), dominants[16]);
+            assert_eq!(("2020-03-18".to_owned(), "AG2012.XSGE".to_owned()), dominants[17]);
+            assert!(fake.requests_of("get_dominant_future").len() < 10);
+        });
+    }
+}
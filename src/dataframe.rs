@@ -0,0 +1,149 @@
+//! DataFrame integration
+//!
+//! Builds polars `DataFrame` directly from csv responses,
+//! enabled by feature "polars".
+//!
+//! Column types are inferred by polars csv reader with date parsing
+//! enabled, so "%Y-%m-%d" columns become Date and times become
+//! Datetime. Security code columns are always read as strings to
+//! keep leading zeros.
+use crate::{CsvListBodyConsumer, Error, HasMethod, JqdataClient, Result};
+use polars::prelude::*;
+use serde::Serialize;
+use std::io::Cursor;
+
+/// name of the security code column added to panels
+pub const CODE_COLUMN: &str = "code";
+
+/// columns that always hold security codes
+const CODE_COLUMNS: &[&str] = &["code", "sec_code", "index"];
+
+fn polars_error(e: PolarsError) -> Error {
+    Error::Client(format!("polars error: {}", e))
+}
+
+/// convert csv body with header into dataframe
+pub fn csv_to_dataframe(body: Vec<u8>) -> Result<DataFrame> {
+    let header_line = body.split(|b| *b == b'\n').next().unwrap_or_default();
+    let header = String::from_utf8(header_line.to_vec())?;
+    if header.is_empty() {
+        return Err(Error::Server("empty response body returned".to_owned()));
+    }
+    if header.starts_with("error") {
+        return Err(Error::Server(header));
+    }
+    let overwrite: Schema = header
+        .trim_end()
+        .split(',')
+        .filter(|col| CODE_COLUMNS.contains(col))
+        .map(|col| Field::new(col.into(), DataType::String))
+        .collect();
+    CsvReadOptions::default()
+        .with_has_header(true)
+        .with_infer_schema_length(None)
+        .with_schema_overwrite(Some(Arc::new(overwrite)))
+        .map_parse_options(|opts| opts.with_try_parse_dates(true))
+        .into_reader_with_file_handle(Cursor::new(body))
+        .finish()
+        .map_err(polars_error)
+}
+
+/// add code column as the first column of dataframe,
+/// dataframe already containing a code column is returned as is
+pub fn with_code_column(mut df: DataFrame, code: &str) -> Result<DataFrame> {
+    if df.get_column_index(CODE_COLUMN).is_some() {
+        return Ok(df);
+    }
+    let codes = Series::new(CODE_COLUMN.into(), vec![code; df.height()]);
+    df.insert_column(0, codes).map_err(polars_error)?;
+    Ok(df)
+}
+
+/// concat per-code dataframes into one panel with code column,
+/// missing columns in some frames are filled with nulls
+pub fn concat_with_code<S: AsRef<str>>(frames: Vec<(S, DataFrame)>) -> Result<DataFrame> {
+    let frames = frames
+        .into_iter()
+        .map(|(code, df)| with_code_column(df, code.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+    if frames.is_empty() {
+        return Ok(DataFrame::empty());
+    }
+    polars::functions::concat_df_diagonal(&frames).map_err(polars_error)
+}
+
+impl JqdataClient {
+    /// Execute request of csv format and return the
+    /// response as dataframe
+    pub async fn execute_df<C>(&self, command: C) -> Result<DataFrame>
+    where
+        C: HasMethod + CsvListBodyConsumer + Serialize,
+    {
+        let body = self.send_command(command).await?;
+        csv_to_dataframe(body)
+    }
+
+    /// Execute one request per code and concat the results
+    /// into a panel with code column
+    pub async fn execute_panel<C, F, S>(&self, codes: &[S], command: F) -> Result<DataFrame>
+    where
+        C: HasMethod + CsvListBodyConsumer + Serialize,
+        F: Fn(&str) -> C,
+        S: AsRef<str>,
+    {
+        let mut frames = Vec::with_capacity(codes.len());
+        for code in codes {
+            let df = self.execute_df(command(code.as_ref())).await?;
+            frames.push((code.as_ref(), df));
+        }
+        concat_with_code(frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FakeResponse, FakeTransport, GetPricePeriod};
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[test]
+    fn test_csv_to_dataframe() {
+        let body = b"date,sec_code,fin_value\n2020-01-02,000001,1.5\n2020-01-03,000001,2\n".to_vec();
+        let df = csv_to_dataframe(body).unwrap();
+        assert_eq!(2, df.height());
+        assert_eq!(&DataType::Date, df.column("date").unwrap().dtype());
+        assert_eq!(&DataType::String, df.column("sec_code").unwrap().dtype());
+        assert_eq!(&DataType::Float64, df.column("fin_value").unwrap().dtype());
+        assert!(csv_to_dataframe(b"error: invalid token".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_execute_panel() {
+        block_on(async {
+            let fake = FakeTransport::new();
+            for (code, close) in &[("000001.XSHE", "16.9"), ("600000.XSHG", "12.5")] {
+                fake.respond(
+                    "get_price_period",
+                    json!({ "code": code }),
+                    FakeResponse::csv(&["date", "close"], &[&["2020-01-02", close]]),
+                );
+            }
+            let client = JqdataClient::with_transport(fake, "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            let panel = client
+                .execute_panel(&["000001.XSHE", "600000.XSHG"], |code| GetPricePeriod {
+                    code: code.to_owned(),
+                    unit: "1d".to_owned(),
+                    date: "2020-01-02".to_owned(),
+                    end_date: "2020-01-02".to_owned(),
+                    fq_ref_date: None,
+                })
+                .await
+                .unwrap();
+            assert_eq!(2, panel.height());
+            assert_eq!(vec!["code", "date", "close"], panel.get_column_names_str());
+        });
+    }
+}
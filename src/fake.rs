@@ -0,0 +1,327 @@
+//! Fake transport
+//!
+//! In-memory implementation of `Transport` for deterministic tests.
+//! Responses are registered by method name and (optionally) a subset
+//! of request parameters, every request sent is recorded for later
+//! inspection.
+use crate::{Error, Result, Transport};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// token returned for get_current_token if no response is registered
+pub const FAKE_TOKEN: &str = "fake-token";
+
+/// FakeResponse
+///
+/// canned response of fake transport
+#[derive(Debug, Clone)]
+pub struct FakeResponse {
+    body: std::result::Result<Vec<u8>, String>,
+    latency: Option<Duration>,
+}
+
+impl FakeResponse {
+    /// response with raw body
+    pub fn body<B: Into<Vec<u8>>>(body: B) -> Self {
+        FakeResponse {
+            body: Ok(body.into()),
+            latency: None,
+        }
+    }
+
+    /// csv response with header and rows
+    pub fn csv(header: &[&str], rows: &[&[&str]]) -> Self {
+        let mut s = header.join(",");
+        for row in rows {
+            s.push('\n');
+            s.push_str(&row.join(","));
+        }
+        Self::body(s)
+    }
+
+    /// csv response serialized from rows
+    pub fn csv_rows<T: Serialize>(rows: &[T]) -> Result<Self> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        for row in rows {
+            writer.serialize(row)?;
+        }
+        let body = writer
+            .into_inner()
+            .map_err(|e| Error::Client(e.to_string()))?;
+        Ok(Self::body(body))
+    }
+
+    /// json response serialized from value
+    pub fn json<T: Serialize>(value: &T) -> Result<Self> {
+        Ok(Self::body(serde_json::to_vec(value)?))
+    }
+
+    /// response with one value per line
+    pub fn lines(lines: &[&str]) -> Self {
+        Self::body(lines.join("\n"))
+    }
+
+    /// response with single value
+    pub fn single<T: ToString>(value: T) -> Self {
+        Self::body(value.to_string())
+    }
+
+    /// error message returned by server, e.g. invalid parameters
+    pub fn server_error(msg: &str) -> Self {
+        Self::body(format!("error: {}", msg))
+    }
+
+    /// error raised by transport, e.g. connection failure
+    pub fn error(msg: &str) -> Self {
+        FakeResponse {
+            body: Err(msg.to_owned()),
+            latency: None,
+        }
+    }
+
+    /// delay the response by given duration
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+}
+
+/// RecordedRequest
+///
+/// request received by fake transport, token and method
+/// are separated from other parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub token: Option<String>,
+    pub params: Map<String, Value>,
+}
+
+struct Route {
+    method: String,
+    params: Map<String, Value>,
+    response: FakeResponse,
+    once: bool,
+}
+
+impl Route {
+    fn matches(&self, req: &RecordedRequest) -> bool {
+        self.method == req.method
+            && self
+                .params
+                .iter()
+                .all(|(k, v)| req.params.get(k) == Some(v))
+    }
+}
+
+#[derive(Default)]
+struct FakeState {
+    routes: Vec<Route>,
+    requests: Vec<RecordedRequest>,
+    latency: Option<Duration>,
+}
+
+/// FakeTransport
+///
+/// Cloned instances share the same routes and records, so one
+/// clone can be passed to the client and the other kept for
+/// inspection.
+/// Routes are matched in registration order, a route matches
+/// if method is equal and all its parameters are present in
+/// the request with equal values.
+#[derive(Clone, Default)]
+pub struct FakeTransport {
+    state: Arc<Mutex<FakeState>>,
+}
+
+impl FakeTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// respond to method with given parameters
+    pub fn respond(&self, method: &str, params: Value, response: FakeResponse) -> &Self {
+        self.add_route(method, params, response, false)
+    }
+
+    /// respond to method regardless of parameters
+    pub fn respond_any(&self, method: &str, response: FakeResponse) -> &Self {
+        self.add_route(method, Value::Null, response, false)
+    }
+
+    /// respond to method with given parameters only once,
+    /// the route is removed after being matched
+    pub fn respond_once(&self, method: &str, params: Value, response: FakeResponse) -> &Self {
+        self.add_route(method, params, response, true)
+    }
+
+    /// delay all responses by given duration,
+    /// latency of single response takes precedence
+    pub fn set_latency(&self, latency: Duration) -> &Self {
+        self.state.lock().unwrap().latency = Some(latency);
+        self
+    }
+
+    /// all requests received so far
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// requests of given method received so far
+    pub fn requests_of(&self, method: &str) -> Vec<RecordedRequest> {
+        self.state
+            .lock()
+            .unwrap()
+            .requests
+            .iter()
+            .filter(|r| r.method == method)
+            .cloned()
+            .collect()
+    }
+
+    /// clear recorded requests
+    pub fn clear_requests(&self) {
+        self.state.lock().unwrap().requests.clear();
+    }
+
+    fn add_route(&self, method: &str, params: Value, response: FakeResponse, once: bool) -> &Self {
+        let params = match params {
+            Value::Object(map) => map,
+            Value::Null => Map::new(),
+            other => panic!("fake route parameters must be an object, got {}", other),
+        };
+        self.state.lock().unwrap().routes.push(Route {
+            method: method.to_owned(),
+            params,
+            response,
+            once,
+        });
+        self
+    }
+
+    fn dispatch(&self, body: &str) -> Result<(FakeResponse, Option<Duration>)> {
+        let req = parse_request(body)?;
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(req.clone());
+        let latency = state.latency;
+        let matched = state.routes.iter().position(|r| r.matches(&req));
+        let response = match matched {
+            Some(idx) if state.routes[idx].once => state.routes.remove(idx).response,
+            Some(idx) => state.routes[idx].response.clone(),
+            None if req.method == "get_current_token" => FakeResponse::body(FAKE_TOKEN),
+            None => {
+                return Err(Error::Client(format!(
+                    "no fake response registered for request {}",
+                    body
+                )))
+            }
+        };
+        Ok((response, latency))
+    }
+}
+
+impl Transport for FakeTransport {
+    fn send(&self, body: String) -> BoxFuture<'_, Result<Vec<u8>>> {
+        let dispatched = self.dispatch(&body);
+        Box::pin(async move {
+            let (response, latency) = dispatched?;
+            if let Some(latency) = response.latency.or(latency) {
+                futures_timer::Delay::new(latency).await;
+            }
+            response.body.map_err(Error::Client)
+        })
+    }
+}
+
+fn parse_request(body: &str) -> Result<RecordedRequest> {
+    let mut params = match serde_json::from_str(body)? {
+        Value::Object(map) => map,
+        other => return Err(Error::Client(format!("request body is not an object: {}", other))),
+    };
+    let method = match params.remove("method") {
+        Some(Value::String(method)) => method,
+        _ => return Err(Error::Client(format!("method not found in request: {}", body))),
+    };
+    let token = match params.remove("token") {
+        Some(Value::String(token)) => Some(token),
+        _ => None,
+    };
+    Ok(RecordedRequest {
+        method,
+        token,
+        params,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GetAllSecurities, GetQueryCount, GetTradeDays, JqdataClient, SecurityKind};
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[test]
+    fn test_fake_transport_matches_params() {
+        block_on(async {
+            let fake = FakeTransport::new();
+            fake.respond(
+                "get_trade_days",
+                json!({"date": "2020-01-01"}),
+                FakeResponse::lines(&["2020-01-02", "2020-01-03"]),
+            )
+            .respond_any(
+                "get_trade_days",
+                FakeResponse::server_error("invalid date"),
+            );
+            let client = JqdataClient::with_transport(fake.clone(), "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            let days = client
+                .execute(GetTradeDays {
+                    date: "2020-01-01".to_owned(),
+                    end_date: Some("2020-01-03".to_owned()),
+                })
+                .await
+                .unwrap();
+            assert_eq!(vec!["2020-01-02".to_owned(), "2020-01-03".to_owned()], days);
+            let ss = client
+                .execute(GetAllSecurities {
+                    code: SecurityKind::Stock,
+                    date: None,
+                })
+                .await;
+            assert!(ss.is_err());
+
+            let requests = fake.requests();
+            assert_eq!(3, requests.len());
+            assert_eq!("get_current_token", requests[0].method);
+            assert_eq!(Some(FAKE_TOKEN.to_owned()), requests[1].token);
+            assert_eq!(Some(&json!("2020-01-03")), requests[1].params.get("end_date"));
+        });
+    }
+
+    #[test]
+    fn test_fake_transport_injects_errors() {
+        block_on(async {
+            let fake = FakeTransport::new();
+            fake.respond_once(
+                "get_query_count",
+                Value::Null,
+                FakeResponse::error("connection reset").with_latency(Duration::from_millis(10)),
+            )
+            .respond_any("get_query_count", FakeResponse::single(1000));
+            let client = JqdataClient::with_transport(fake.clone(), "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            match client.execute(GetQueryCount {}).await {
+                Err(Error::Client(msg)) => assert_eq!("connection reset", msg),
+                other => panic!("unexpected result {:?}", other),
+            }
+            assert_eq!(1000, client.execute(GetQueryCount {}).await.unwrap());
+            assert_eq!(2, fake.requests_of("get_query_count").len());
+        });
+    }
+}
@@ -0,0 +1,6 @@
+//! Futures
+//!
+//! Helpers built on futures contracts, such as continuous series.
+pub mod continuous;
+
+pub use self::continuous::*;
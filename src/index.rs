@@ -0,0 +1,318 @@
+//! Index history
+//!
+//! Point-in-time constituents and weights of an index, so backtests
+//! can use the members of each date instead of today's members.
+//!
+//! Constituents are sampled every few trade days with
+//! get_index_stocks. Where two samples differ, the trade days in
+//! between are bisected until the first day of the new membership
+//! is found, so additions and deletions get their effective date at
+//! the cost of a few requests per change. Weights are published
+//! monthly and are sampled on the same days without bisection.
+//!
+//! A change that is reverted before the next sample, such as a
+//! temporary replacement, looks the same in both samples and is
+//! not detected; sample more often to catch short-lived changes.
+use crate::{GetIndexStocks, GetIndexWeights, GetTradeDays, IndexWeight, JqdataClient, Result};
+use serde_derive::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// members of an index from date until the next snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexSnapshot {
+    pub date: String,
+    pub members: BTreeSet<String>,
+}
+
+/// membership change effective on date
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexChange {
+    pub date: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// IndexHistory
+///
+/// constituents and weights of one index between two dates,
+/// can be saved and queried offline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexHistory {
+    pub index: String,
+    pub start_date: String,
+    pub end_date: String,
+    /// snapshots in date order, one per membership change
+    pub snapshots: Vec<IndexSnapshot>,
+    pub changes: Vec<IndexChange>,
+    /// weights by their publication date
+    pub weights: BTreeMap<String, Vec<IndexWeight>>,
+}
+
+impl IndexHistory {
+    /// members on date, None outside the covered range
+    pub fn members_on(&self, date: &str) -> Option<&BTreeSet<String>> {
+        if date > self.end_date.as_str() {
+            return None;
+        }
+        self.snapshots.iter().rev().find(|s| s.date.as_str() <= date).map(|s| &s.members)
+    }
+
+    /// whether code was a member on date
+    pub fn is_member(&self, code: &str, date: &str) -> bool {
+        self.members_on(date).map(|m| m.contains(code)).unwrap_or(false)
+    }
+
+    /// latest weights published on or before date
+    pub fn weights_on(&self, date: &str) -> Option<&[IndexWeight]> {
+        self.weights.range(..=date.to_owned()).next_back().map(|(_, w)| w.as_slice())
+    }
+
+    /// changes effective between start and end date, inclusive
+    pub fn changes_between(&self, start_date: &str, end_date: &str) -> impl Iterator<Item = &IndexChange> {
+        let (start, end) = (start_date.to_owned(), end_date.to_owned());
+        self.changes.iter().filter(move |c| c.date >= start && c.date <= end)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+/// IndexHistoryBuilder
+///
+/// samples constituents every 20 trade days and bisects changes
+/// by default, weights are not fetched unless enabled
+#[derive(Clone)]
+pub struct IndexHistoryBuilder {
+    client: JqdataClient,
+    every: usize,
+    bisect: bool,
+    weights: bool,
+}
+
+impl IndexHistoryBuilder {
+    pub fn new(client: JqdataClient) -> Self {
+        IndexHistoryBuilder {
+            client,
+            every: 20,
+            bisect: true,
+            weights: false,
+        }
+    }
+
+    /// trade days between samples, membership changes reverted
+    /// within fewer trade days may be missed
+    pub fn every(mut self, every: usize) -> Self {
+        self.every = every.max(1);
+        self
+    }
+
+    /// without bisection a change is dated to the first
+    /// sample showing it
+    pub fn bisect(mut self, bisect: bool) -> Self {
+        self.bisect = bisect;
+        self
+    }
+
+    pub fn weights(mut self, weights: bool) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    async fn members(&self, index: &str, date: &str) -> Result<BTreeSet<String>> {
+        let lines = self
+            .client
+            .execute(GetIndexStocks {
+                code: index.to_owned(),
+                date: date.to_owned(),
+            })
+            .await?;
+        Ok(lines
+            .into_iter()
+            .map(|l| l.trim().to_owned())
+            .filter(|l| !l.is_empty())
+            .collect())
+    }
+
+    /// build history of index, such as "000300.XSHG"
+    pub async fn build(&self, index: &str, start_date: &str, end_date: &str) -> Result<IndexHistory> {
+        let days = self
+            .client
+            .execute(GetTradeDays {
+                date: start_date.to_owned(),
+                end_date: Some(end_date.to_owned()),
+            })
+            .await?;
+        let mut samples: Vec<usize> = (0..days.len()).step_by(self.every).collect();
+        if !days.is_empty() && samples.last() != Some(&(days.len() - 1)) {
+            samples.push(days.len() - 1);
+        }
+        // members by index of trade day
+        let mut fetched: BTreeMap<usize, BTreeSet<String>> = BTreeMap::new();
+        for &i in &samples {
+            let members = self.members(index, &days[i]).await?;
+            fetched.insert(i, members);
+        }
+        if self.bisect {
+            let mut pending: Vec<(usize, usize)> = samples.windows(2).map(|w| (w[0], w[1])).collect();
+            while let Some((lo, hi)) = pending.pop() {
+                if hi - lo < 2 || fetched[&lo] == fetched[&hi] {
+                    continue;
+                }
+                let mid = lo + (hi - lo) / 2;
+                let members = self.members(index, &days[mid]).await?;
+                fetched.insert(mid, members);
+                pending.push((lo, mid));
+                pending.push((mid, hi));
+            }
+        }
+
+        let mut snapshots: Vec<IndexSnapshot> = Vec::new();
+        let mut changes = Vec::new();
+        for (i, members) in fetched {
+            if let Some(prev) = snapshots.last() {
+                if prev.members == members {
+                    continue;
+                }
+                changes.push(IndexChange {
+                    date: days[i].clone(),
+                    added: members.difference(&prev.members).cloned().collect(),
+                    removed: prev.members.difference(&members).cloned().collect(),
+                });
+            }
+            snapshots.push(IndexSnapshot {
+                date: days[i].clone(),
+                members,
+            });
+        }
+
+        let mut weights = BTreeMap::new();
+        if self.weights {
+            for &i in &samples {
+                let rows = self
+                    .client
+                    .execute(GetIndexWeights {
+                        code: index.to_owned(),
+                        date: days[i].clone(),
+                    })
+                    .await?;
+                if let Some(first) = rows.first() {
+                    weights.insert(first.date.clone(), rows);
+                }
+            }
+        }
+        Ok(IndexHistory {
+            index: index.to_owned(),
+            start_date: start_date.to_owned(),
+            end_date: end_date.to_owned(),
+            snapshots,
+            changes,
+            weights,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FakeResponse, FakeTransport};
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    const DAYS: &[&str] = &["2020-01-02", "2020-01-03", "2020-01-06", "2020-01-07", "2020-01-08", "2020-01-09"];
+
+    fn fake() -> FakeTransport {
+        let fake = FakeTransport::new();
+        fake.respond("get_trade_days", json!({}), FakeResponse::lines(DAYS));
+        // 000002 replaced by 000003 effective 2020-01-07
+        for (i, day) in DAYS.iter().enumerate() {
+            let members = if i < 3 { ["000001.XSHE", "000002.XSHE"] } else { ["000001.XSHE", "000003.XSHE"] };
+            fake.respond("get_index_stocks", json!({ "date": day }), FakeResponse::lines(&members));
+        }
+        fake.respond(
+            "get_index_weights",
+            json!({"date": "2020-01-02"}),
+            FakeResponse::csv(
+                &["code", "display_name", "date", "weight"],
+                &[&["000001.XSHE", "平安银行", "2019-12-31", "60"], &["000002.XSHE", "万科A", "2019-12-31", "40"]],
+            ),
+        );
+        fake.respond(
+            "get_index_weights",
+            json!({}),
+            FakeResponse::csv(
+                &["code", "display_name", "date", "weight"],
+                &[&["000001.XSHE", "平安银行", "2020-01-08", "55"], &["000003.XSHE", "国农科技", "2020-01-08", "45"]],
+            ),
+        );
+        fake
+    }
+
+    #[test]
+    fn test_build_index_history() {
+        let fake = fake();
+        let history = block_on(async {
+            let client = JqdataClient::with_transport(fake.clone(), "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            IndexHistoryBuilder::new(client)
+                .every(5)
+                .weights(true)
+                .build("000300.XSHG", "2020-01-02", "2020-01-09")
+                .await
+        })
+        .unwrap();
+        assert_eq!(
+            vec![IndexChange {
+                date: "2020-01-07".to_owned(),
+                added: vec!["000003.XSHE".to_owned()],
+                removed: vec!["000002.XSHE".to_owned()],
+            }],
+            history.changes
+        );
+        // samples on day 0 and 5, bisected on days 2 and 3
+        assert_eq!(4, fake.requests_of("get_index_stocks").len());
+        assert!(history.is_member("000002.XSHE", "2020-01-06"));
+        assert!(!history.is_member("000002.XSHE", "2020-01-07"));
+        assert!(history.members_on("2020-01-01").is_none());
+        assert!(history.members_on("2020-01-10").is_none());
+        assert_eq!("40", history.weights_on("2020-01-07").unwrap()[1].weight.to_string());
+        assert_eq!("000003.XSHE", history.weights_on("2020-01-09").unwrap()[1].code);
+        assert_eq!(1, history.changes_between("2020-01-01", "2020-01-07").count());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("000300.XSHG.json");
+        history.save(&path).unwrap();
+        assert_eq!(history, IndexHistory::load(&path).unwrap());
+    }
+
+    #[test]
+    fn test_without_bisection() {
+        let fake = fake();
+        let history = block_on(async {
+            let client = JqdataClient::with_transport(fake.clone(), "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            IndexHistoryBuilder::new(client)
+                .every(5)
+                .bisect(false)
+                .build("000300.XSHG", "2020-01-02", "2020-01-09")
+                .await
+        })
+        .unwrap();
+        assert_eq!("2020-01-09", history.changes[0].date);
+        assert_eq!(2, fake.requests_of("get_index_stocks").len());
+    }
+}
@@ -1,32 +1,62 @@
 pub use jqdata_model::*;
+/// derive request types, use `#[jqdata(crate = "jqdata")]`
+/// if jqdata-model is not a direct dependency
+pub use jqdata_derive::Jqdata;
+
+pub mod adjust;
+pub mod bars;
+pub mod bulk;
+pub mod cache;
+pub mod calendar;
+pub mod cassette;
+#[cfg(feature = "arrow")]
+pub mod columnar;
+pub mod continuous_futures;
+#[cfg(feature = "polars")]
+pub mod dataframe;
+pub mod fake;
+pub mod index;
+pub mod live;
+pub mod orderbook;
+mod raw;
+pub mod store;
+pub mod subscription;
+pub mod transport;
+pub mod universe;
+
+pub use crate::adjust::*;
+pub use crate::bars::*;
+pub use crate::bulk::*;
+pub use crate::cache::*;
+pub use crate::calendar::*;
+pub use crate::cassette::*;
+#[cfg(feature = "arrow")]
+pub use crate::columnar::*;
+pub use crate::continuous_futures::{Adjustment, ContinuousFutures, Roll};
+#[cfg(feature = "polars")]
+pub use crate::dataframe::*;
+pub use crate::fake::*;
+pub use crate::index::*;
+pub use crate::live::*;
+pub use crate::orderbook::*;
+pub use crate::store::*;
+pub use crate::subscription::*;
+pub use crate::transport::*;
+pub use crate::universe::*;
 
-#[cfg(test)]
-use mockito;
-use reqwest::header::{HeaderValue, CONTENT_TYPE};
 use serde_json::json;
 use std::sync::Arc;
 use futures_util::lock::Mutex;
 use serde::{Serialize, Deserialize};
 
-/// provide static jqdata API url
-/// 
-/// use #[cfg(test)] to switch this address with mockito address
-#[cfg(not(test))]
-fn jqdata_url() -> String {
-    String::from("https://dataapi.joinquant.com/apis")
-}
-
-#[cfg(test)]
-fn jqdata_url() -> String {
-    mockito::server_url()
-}
-
 /// JqdataClient
 /// 
 /// async client for jqdata API
 #[derive(Clone)]
 pub struct JqdataClient {
     inner: Arc<Mutex<Arc<SharedClient>>>,
+    transport: Arc<dyn Transport>,
+    schema_policy: SchemaPolicy,
 }
 
 impl JqdataClient {
@@ -34,27 +64,57 @@ impl JqdataClient {
     /// Create new client with given credential
     /// 
     /// This method will try to refresh token using the given
-    /// credential, causing itself to be async
+    /// credential, causing itself to be async.
+    /// The transport is selected by enabled features
+    #[cfg(any(feature = "reqwest", feature = "ureq"))]
     pub async fn with_credential(mob: String, pwd: String) -> Result<Self> {
+        Self::with_transport(crate::transport::default_transport(), mob, pwd).await
+    }
+
+    /// Create new client with given transport and credential
+    pub async fn with_transport<T>(transport: T, mob: String, pwd: String) -> Result<Self>
+    where
+        T: Transport + 'static,
+    {
+        let transport: Arc<dyn Transport> = Arc::new(transport);
         let mut shared_cli = SharedClient{
             credential: Some(ClientCredential{ mob, pwd }),
             token: String::new(),
         };
-        shared_cli.refresh_token().await?;
+        shared_cli.refresh_token(&*transport).await?;
         Ok(JqdataClient{
             inner: Arc::new(Mutex::new(Arc::new(shared_cli))),
+            transport,
+            schema_policy: SchemaPolicy::default(),
         })
     }
 
-    /// Execute request in async context, 
+    /// Set policy applied when csv columns of responses differ
+    /// from the row types, lenient by default
+    pub fn with_schema_policy(mut self, policy: SchemaPolicy) -> Self {
+        self.schema_policy = policy;
+        self
+    }
+
+    /// Execute request in async context
     /// 
-    /// Aync context should be tokio 0.2, because the reqwest crate 
-    /// depends on it
+    /// The runtime requirement depends on the transport,
+    /// see module transport for details
     pub async fn execute<T, C>(&self, command: C) -> Result<T> 
     where 
         T: for<'de> Deserialize<'de>,
         T: Serialize,
         C: HasMethod + BodyConsumer<T> + Serialize,
+    {
+        let response = self.send_command(command).await?;
+        let output = <C as BodyConsumer<_>>::consume_body_with_policy(response.as_slice(), self.schema_policy)?;
+        Ok(output)
+    }
+
+    /// send request with current token and return raw response body
+    pub(crate) async fn send_command<C>(&self, command: C) -> Result<Vec<u8>>
+    where
+        C: HasMethod + Serialize,
     {
         let shared_cli = {
             let cli_ref = &*self.inner.lock().await;
@@ -62,19 +122,7 @@ impl JqdataClient {
         };
         let req_body = Request::new(shared_cli.token.to_owned(), command);
         let body = serde_json::to_string(&req_body)?;
-        let client = reqwest::Client::new();
-        let response = client
-            .post(&crate::jqdata_url())
-            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
-            .body(body)
-            .send()
-            .await
-            .map_err(|e| Error::Client(e.to_string()))?
-            .text()
-            .await
-            .map_err(|e| Error::Client(e.to_string()))?;
-        let output = <C as BodyConsumer<_>>::consume_body(response.as_bytes())?;
-        Ok(output)
+        self.transport.send(body).await
     }
 }
 
@@ -84,7 +132,7 @@ struct SharedClient {
 }
 
 impl SharedClient {
-    async fn refresh_token(&mut self) -> Result<()> {
+    async fn refresh_token(&mut self, transport: &dyn Transport) -> Result<()> {
         if self.credential.is_none() {
             return Err(Error::Client("credential not available to refresh token".to_owned()));
         }
@@ -95,15 +143,8 @@ impl SharedClient {
             "pwd": self.credential.as_ref().unwrap().pwd,
         });
 
-        let client = reqwest::Client::new();
-        let response = client
-            .post(&jqdata_url())
-            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
-            .body(token_req.to_string())
-            .send()
-            .await
-            .map_err(|e| Error::Client(e.to_string()))?;
-        let token = response.text().await.map_err(|e| Error::Client(e.to_string()))?;
+        let response = transport.send(token_req.to_string()).await?;
+        let token = String::from_utf8(response)?;
         if token.starts_with("error") {
             return Err(Error::Server(token));
         }
@@ -118,7 +159,7 @@ struct ClientCredential {
     pwd: String,
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "reqwest"))]
 mod tests {
     use super::*;
     use mockito::mock;
@@ -142,7 +183,8 @@ mod tests {
                 .with_status(200)
                 .with_body("abc")
                 .create();
-            JqdataClient::with_credential("10000".to_owned(), "pass".to_owned()).await.unwrap()
+            let transport = ReqwestTransport::with_url(mockito::server_url());
+            JqdataClient::with_transport(transport, "10000".to_owned(), "pass".to_owned()).await.unwrap()
         };
         let ss = client
             .execute(GetAllSecurities {
@@ -161,6 +203,7 @@ mod tests {
                     end_date: "2200-01-01".to_string(),
                     kind: SecurityKind::Stock,
                     parent: None,
+                    extra: Default::default(),
                 },
                 Security {
                     code: "000002.XSHE".to_string(),
@@ -170,6 +213,7 @@ mod tests {
                     end_date: "2200-01-01".to_string(),
                     kind: SecurityKind::Stock,
                     parent: None,
+                    extra: Default::default(),
                 }
             ],
             ss
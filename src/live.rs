@@ -0,0 +1,281 @@
+//! Live bars
+//!
+//! Minute bars of many securities built from polled current ticks,
+//! available as soon as a minute ends instead of when get_price
+//! catches up.
+//!
+//! Each security has its own `TickBarBuilder` of one minute bars,
+//! so bars follow the trading sessions of its market. A bar is
+//! completed by the first tick of a later bar, or by the clock once
+//! its end plus a grace period for late snapshots has passed,
+//! whichever comes first. Ticks arriving after their bar was
+//! completed add to the next bar.
+//!
+//! Polling usually starts in the middle of a session, when the
+//! cumulative volume of the first tick covers the whole day so
+//! far. The first tick of each code is therefore only a baseline
+//! for the volume and amount of later ticks and makes no bar.
+use crate::calendar::beijing_now;
+use crate::{code_group, BarInterval, CodeGroup, Market, Price, Result, Tick, TickBarBuilder, TradingCalendar};
+use chrono::{Duration, NaiveDateTime};
+use futures::channel::oneshot;
+use futures::stream::{self, Stream, StreamExt};
+use futures_timer::Delay;
+use std::collections::HashMap;
+
+/// guess trading hours of security from its code
+///
+/// commodity futures have no night session here, as its end
+/// depends on the product; set their market explicitly
+pub fn market_of(code: &str) -> Market {
+    let (symbol, exchange) = code.split_once('.').unwrap_or((code, ""));
+    match (code_group(code), exchange) {
+        (CodeGroup::Futures, "CCFX") | (CodeGroup::Options, "CCFX") => {
+            let product: String = symbol.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+            if ["T", "TF", "TS", "TL"].contains(&product.as_str()) {
+                Market::TreasuryFutures
+            } else {
+                Market::IndexFutures
+            }
+        }
+        (CodeGroup::Futures, _) | (CodeGroup::Options, "XSGE" | "XDCE" | "XZCE" | "XINE" | "GFEX") => {
+            Market::CommodityFutures { night_end: None }
+        }
+        _ => Market::Stock,
+    }
+}
+
+/// completed minute bar of a security
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveBar {
+    pub code: String,
+    pub bar: Price,
+}
+
+/// LiveBars
+///
+/// one minute bars per code, markets are guessed from codes
+/// unless set, grace period is 5 seconds by default
+#[derive(Debug, Clone)]
+pub struct LiveBars {
+    calendar: TradingCalendar,
+    grace: Duration,
+    markets: HashMap<String, Market>,
+    builders: HashMap<String, TickBarBuilder>,
+}
+
+impl LiveBars {
+    pub fn new(calendar: TradingCalendar) -> Self {
+        LiveBars {
+            calendar,
+            grace: Duration::seconds(5),
+            markets: HashMap::new(),
+            builders: HashMap::new(),
+        }
+    }
+
+    /// time to wait for late snapshots after a bar ends
+    pub fn grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    /// trading hours of code
+    pub fn market<S: Into<String>>(mut self, code: S, market: Market) -> Self {
+        self.markets.insert(code.into(), market);
+        self
+    }
+
+    /// add tick of any code, returns the bar completed by it,
+    /// ticks without code are ignored and the first tick of a code
+    /// is only a baseline
+    pub fn push(&mut self, tick: &Tick) -> Result<Option<LiveBar>> {
+        let code = match tick.code.as_ref() {
+            Some(code) => code,
+            None => return Ok(None),
+        };
+        if !self.builders.contains_key(code) {
+            let market = self.markets.get(code).cloned().unwrap_or_else(|| market_of(code));
+            let mut builder = TickBarBuilder::new(self.calendar.clone(), market, BarInterval::Time(Duration::minutes(1)));
+            builder.baseline(tick)?;
+            self.builders.insert(code.clone(), builder);
+            return Ok(None);
+        }
+        let bar = self.builders.get_mut(code).unwrap().push(tick)?;
+        Ok(bar.map(|bar| LiveBar {
+            code: code.clone(),
+            bar,
+        }))
+    }
+
+    /// complete bars that ended more than the grace period before
+    /// now, in Beijing time
+    pub fn close_until(&mut self, now: NaiveDateTime) -> Vec<LiveBar> {
+        let before = now - self.grace;
+        let mut bars: Vec<LiveBar> = self
+            .builders
+            .iter_mut()
+            .filter_map(|(code, builder)| {
+                builder.close_before(before).map(|bar| LiveBar {
+                    code: code.clone(),
+                    bar,
+                })
+            })
+            .collect();
+        bars.sort_by(|a, b| (&a.bar.date, &a.code).cmp(&(&b.bar.date, &b.code)));
+        bars
+    }
+
+    /// bar in progress of code
+    pub fn current(&self, code: &str) -> Option<Price> {
+        self.builders.get(code).and_then(|b| b.current())
+    }
+
+    /// completed bars from a tick stream such as
+    /// `JqdataClient::subscribe`, the clock is checked every
+    /// `clock` interval
+    ///
+    /// errors of the tick stream are passed through, the stream
+    /// ends with the tick stream after a last check of the clock
+    pub fn stream<S>(self, ticks: S, clock: std::time::Duration) -> impl Stream<Item = Result<LiveBar>>
+    where
+        S: Stream<Item = Result<Tick>>,
+    {
+        let (ended, ticks_ended) = oneshot::channel::<()>();
+        // None for a clock event
+        let clock = stream::unfold((), move |_| async move {
+            Delay::new(clock).await;
+            Some((None, ()))
+        })
+        .take_until(ticks_ended);
+        let ticks = ticks.map(Some).chain(stream::once(async move {
+            let _ = ended.send(());
+            None
+        }));
+        stream::select(ticks, clock)
+            .scan(self, |live, event| {
+                let bars: Vec<Result<LiveBar>> = match event {
+                    Some(Ok(tick)) => match live.push(&tick) {
+                        Ok(bar) => bar.into_iter().map(Ok).collect(),
+                        Err(e) => vec![Err(e)],
+                    },
+                    Some(Err(e)) => vec![Err(e)],
+                    None => live.close_until(beijing_now()).into_iter().map(Ok).collect(),
+                };
+                futures::future::ready(Some(stream::iter(bars)))
+            })
+            .flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Numeric;
+    use chrono::NaiveDate;
+    use futures::executor::block_on;
+
+    fn tick(code: &str, time: &str, current: f64, volume: f64) -> Tick {
+        let n = |v: f64| Numeric::from_f64(v);
+        Tick {
+            code: Some(code.to_owned()),
+            time: time.to_owned(),
+            current: n(current),
+            high: n(current),
+            low: n(current),
+            volume: n(volume),
+            money: n(volume * current),
+            position: None,
+            a1_v: None,
+            a2_v: None,
+            a3_v: None,
+            a4_v: None,
+            a5_v: None,
+            a1_p: None,
+            a2_p: None,
+            a3_p: None,
+            a4_p: None,
+            a5_p: None,
+            b1_v: None,
+            b2_v: None,
+            b3_v: None,
+            b4_v: None,
+            b5_v: None,
+            b1_p: None,
+            b2_p: None,
+            b3_p: None,
+            b4_p: None,
+            b5_p: None,
+            extra: Default::default(),
+        }
+    }
+
+    fn calendar() -> TradingCalendar {
+        TradingCalendar::from_lines(&["2020-01-02", "2020-01-03"]).unwrap()
+    }
+
+    #[test]
+    fn test_market_of() {
+        assert_eq!(Market::Stock, market_of("000001.XSHE"));
+        assert_eq!(Market::IndexFutures, market_of("IF2001.CCFX"));
+        assert_eq!(Market::TreasuryFutures, market_of("T2003.CCFX"));
+        assert_eq!(Market::CommodityFutures { night_end: None }, market_of("AG2006.XSGE"));
+    }
+
+    #[test]
+    fn test_close_by_tick_and_clock() {
+        let at = |h: u32, m: u32, s: u32| NaiveDate::from_ymd_opt(2020, 1, 2).unwrap().and_hms_opt(h, m, s).unwrap();
+        let mut live = LiveBars::new(calendar());
+        for code in &["000001.XSHE", "600000.XSHG"] {
+            assert_eq!(None, live.push(&tick(code, "20200102093000.0", 16.5, 0.0)).unwrap());
+        }
+        assert_eq!(None, live.push(&tick("000001.XSHE", "20200102093003.0", 16.6, 100.0)).unwrap());
+        assert_eq!(None, live.push(&tick("600000.XSHG", "20200102093004.0", 12.5, 100.0)).unwrap());
+        // next minute completes the bar of 000001
+        let bar = live.push(&tick("000001.XSHE", "20200102093103.0", 16.7, 150.0)).unwrap().unwrap();
+        assert_eq!(("000001.XSHE", "2020-01-02 09:31:00"), (bar.code.as_str(), bar.bar.date.as_str()));
+        // bars of 600000 and 000001 wait for the grace period
+        assert!(live.close_until(at(9, 31, 4)).is_empty());
+        let closed = live.close_until(at(9, 31, 6));
+        assert_eq!(1, closed.len());
+        assert_eq!("600000.XSHG", closed[0].code);
+        // late snapshot of a closed bar counts in the next one
+        assert_eq!(None, live.push(&tick("600000.XSHG", "20200102093059.0", 12.6, 130.0)).unwrap());
+        assert_eq!(None, live.push(&tick("600000.XSHG", "20200102093106.0", 12.7, 160.0)).unwrap());
+        assert_eq!(60.0, live.current("600000.XSHG").unwrap().volume.to_f64());
+        let closed = live.close_until(at(9, 32, 10));
+        assert_eq!(vec!["000001.XSHE", "600000.XSHG"], closed.iter().map(|b| b.code.as_str()).collect::<Vec<_>>());
+        assert_eq!(50.0, closed[0].bar.volume.to_f64());
+    }
+
+    #[test]
+    fn test_stream() {
+        let ticks = stream::iter(vec![
+            Ok(tick("000001.XSHE", "20200102093000.0", 16.5, 0.0)),
+            Ok(tick("000001.XSHE", "20200102093003.0", 16.6, 100.0)),
+            Ok(tick("000001.XSHE", "20200102093103.0", 16.7, 150.0)),
+        ]);
+        // ends with the ticks, the last check of the clock completes
+        // the bar in progress
+        let bars: Vec<Result<LiveBar>> = block_on(
+            LiveBars::new(calendar())
+                .stream(ticks, std::time::Duration::from_millis(1))
+                .collect(),
+        );
+        let dates: Vec<String> = bars.into_iter().map(|b| b.unwrap().bar.date).collect();
+        assert_eq!(vec!["2020-01-02 09:31:00", "2020-01-02 09:32:00"], dates);
+    }
+
+    #[test]
+    fn test_first_tick_is_baseline() {
+        let mut live = LiveBars::new(calendar());
+        // polling starts mid-session, the day so far is not a bar
+        assert_eq!(None, live.push(&tick("000001.XSHE", "20200102101500.0", 16.6, 1e7)).unwrap());
+        assert_eq!(None, live.current("000001.XSHE"));
+        assert_eq!(None, live.push(&tick("000001.XSHE", "20200102101530.0", 16.7, 1e7 + 200.0)).unwrap());
+        let bar = live.push(&tick("000001.XSHE", "20200102101603.0", 16.8, 1e7 + 300.0)).unwrap().unwrap();
+        assert_eq!("2020-01-02 10:16:00", bar.bar.date);
+        assert_eq!(200.0, bar.bar.volume.to_f64());
+        assert_eq!(16.7, bar.bar.open.to_f64());
+    }
+}
@@ -0,0 +1,335 @@
+//! Order book
+//!
+//! Level view of the five quote levels of tick snapshots, with
+//! spread, mid and micro price, imbalance and the changes between
+//! consecutive snapshots.
+//!
+//! Snapshots only show the best levels and the cumulative volume,
+//! so changes are inferred: volume traded since the previous
+//! snapshot is taken from the levels on the side hit by the trade,
+//! best prices first, the remaining decrease counts as cancelled.
+//! Levels outside the visible depth of either snapshot are not
+//! compared.
+use crate::{Numeric, Number, Tick};
+
+/// side of the book
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+/// price level
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Level {
+    pub price: f64,
+    pub volume: f64,
+}
+
+/// OrderBook
+///
+/// quote levels of one tick, best level first
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBook {
+    pub time: String,
+    pub last: f64,
+    /// cumulative volume and amount of the trade day
+    pub volume: f64,
+    pub money: f64,
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+fn levels(prices: [&Option<Number>; 5], volumes: [&Option<Number>; 5]) -> Vec<Level> {
+    prices
+        .iter()
+        .zip(volumes.iter())
+        .map_while(|(p, v)| match (p, v) {
+            (Some(p), Some(v)) if p.to_f64() > 0.0 => Some(Level {
+                price: p.to_f64(),
+                volume: v.to_f64(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+impl From<&Tick> for OrderBook {
+    fn from(t: &Tick) -> Self {
+        OrderBook {
+            time: t.time.clone(),
+            last: t.current.to_f64(),
+            volume: t.volume.to_f64(),
+            money: t.money.to_f64(),
+            bids: levels(
+                [&t.b1_p, &t.b2_p, &t.b3_p, &t.b4_p, &t.b5_p],
+                [&t.b1_v, &t.b2_v, &t.b3_v, &t.b4_v, &t.b5_v],
+            ),
+            asks: levels(
+                [&t.a1_p, &t.a2_p, &t.a3_p, &t.a4_p, &t.a5_p],
+                [&t.a1_v, &t.a2_v, &t.a3_v, &t.a4_v, &t.a5_v],
+            ),
+        }
+    }
+}
+
+impl OrderBook {
+    pub fn levels(&self, side: Side) -> &[Level] {
+        match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        }
+    }
+
+    /// pairs of bid and ask levels, best first, as deep
+    /// as both sides go
+    pub fn iter_levels(&self) -> impl Iterator<Item = (&Level, &Level)> {
+        self.bids.iter().zip(self.asks.iter())
+    }
+
+    pub fn best_bid(&self) -> Option<Level> {
+        self.bids.first().cloned()
+    }
+
+    pub fn best_ask(&self) -> Option<Level> {
+        self.asks.first().cloned()
+    }
+
+    pub fn spread(&self) -> Option<f64> {
+        Some(self.best_ask()?.price - self.best_bid()?.price)
+    }
+
+    pub fn mid(&self) -> Option<f64> {
+        Some((self.best_ask()?.price + self.best_bid()?.price) / 2.0)
+    }
+
+    /// best prices weighted by the volume on the opposite side
+    pub fn microprice(&self) -> Option<f64> {
+        let (bid, ask) = (self.best_bid()?, self.best_ask()?);
+        let total = bid.volume + ask.volume;
+        if total <= 0.0 {
+            return self.mid();
+        }
+        Some((bid.price * ask.volume + ask.price * bid.volume) / total)
+    }
+
+    /// (bid - ask) / (bid + ask) of volumes on the first depth
+    /// levels, level n weighted by 1/n, in [-1, 1]
+    pub fn imbalance(&self, depth: usize) -> Option<f64> {
+        let weighted = |levels: &[Level]| -> f64 {
+            levels
+                .iter()
+                .take(depth)
+                .enumerate()
+                .map(|(i, l)| l.volume / (i + 1) as f64)
+                .sum()
+        };
+        let (bid, ask) = (weighted(&self.bids), weighted(&self.asks));
+        if bid + ask <= 0.0 {
+            return None;
+        }
+        Some((bid - ask) / (bid + ask))
+    }
+
+    /// changes from previous snapshot of the same trade day
+    pub fn diff(&self, prev: &OrderBook) -> BookDiff {
+        let volume = (self.volume - prev.volume).max(0.0);
+        let money = (self.money - prev.money).max(0.0);
+        // trades at or above the previous mid were buyer initiated
+        let aggressor = match prev.mid() {
+            Some(mid) if volume > 0.0 => Some(if self.last >= mid { Side::Ask } else { Side::Bid }),
+            _ => None,
+        };
+        let mut diff = BookDiff {
+            volume,
+            money,
+            aggressor,
+            trades: Vec::new(),
+            cancels: Vec::new(),
+            adds: Vec::new(),
+        };
+        for side in [Side::Bid, Side::Ask] {
+            let mut unfilled = if aggressor == Some(side) { volume } else { 0.0 };
+            for (price, delta) in level_changes(prev.levels(side), self.levels(side), side) {
+                if delta > 0.0 {
+                    diff.adds.push(LevelChange { side, price, volume: delta });
+                    continue;
+                }
+                let traded = unfilled.min(-delta);
+                unfilled -= traded;
+                if traded > 0.0 {
+                    diff.trades.push(LevelChange { side, price, volume: traded });
+                }
+                if -delta > traded {
+                    diff.cancels.push(LevelChange {
+                        side,
+                        price,
+                        volume: -delta - traded,
+                    });
+                }
+            }
+        }
+        diff
+    }
+}
+
+/// volume change at a price level
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelChange {
+    pub side: Side,
+    pub price: f64,
+    pub volume: f64,
+}
+
+/// BookDiff
+///
+/// changes between two snapshots, volumes of trades and
+/// cancels are positive
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookDiff {
+    /// volume and amount traded in between
+    pub volume: f64,
+    pub money: f64,
+    /// side whose quotes were hit, None without trades
+    pub aggressor: Option<Side>,
+    pub trades: Vec<LevelChange>,
+    pub cancels: Vec<LevelChange>,
+    pub adds: Vec<LevelChange>,
+}
+
+/// (price, new - old volume) of levels visible in both snapshots,
+/// best price first
+fn level_changes(old: &[Level], new: &[Level], side: Side) -> Vec<(f64, f64)> {
+    // worse is further from the touch
+    let worse = |a: f64, b: f64| match side {
+        Side::Bid => a < b,
+        Side::Ask => a > b,
+    };
+    let limit = match (old.last(), new.last()) {
+        (Some(o), Some(n)) => {
+            if worse(o.price, n.price) {
+                n.price
+            } else {
+                o.price
+            }
+        }
+        _ => return Vec::new(),
+    };
+    let mut prices: Vec<f64> = old
+        .iter()
+        .chain(new.iter())
+        .map(|l| l.price)
+        .filter(|p| !worse(*p, limit))
+        .collect();
+    prices.sort_by(|a, b| {
+        let ord = a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal);
+        if side == Side::Bid {
+            ord.reverse()
+        } else {
+            ord
+        }
+    });
+    prices.dedup();
+    let volume_at = |levels: &[Level], price: f64| levels.iter().find(|l| l.price == price).map(|l| l.volume).unwrap_or(0.0);
+    prices
+        .into_iter()
+        .map(|p| (p, volume_at(new, p) - volume_at(old, p)))
+        .filter(|(_, delta)| *delta != 0.0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(time: &str, current: f64, volume: f64, bids: &[(f64, f64)], asks: &[(f64, f64)]) -> Tick {
+        let n = |v: f64| Some(Numeric::from_f64(v));
+        let p = |levels: &[(f64, f64)], i: usize| levels.get(i).and_then(|l| n(l.0));
+        let v = |levels: &[(f64, f64)], i: usize| levels.get(i).and_then(|l| n(l.1));
+        Tick {
+            code: None,
+            time: time.to_owned(),
+            current: Numeric::from_f64(current),
+            high: Numeric::from_f64(current),
+            low: Numeric::from_f64(current),
+            volume: Numeric::from_f64(volume),
+            money: Numeric::from_f64(volume * current),
+            position: None,
+            a1_v: v(asks, 0),
+            a2_v: v(asks, 1),
+            a3_v: v(asks, 2),
+            a4_v: v(asks, 3),
+            a5_v: v(asks, 4),
+            a1_p: p(asks, 0),
+            a2_p: p(asks, 1),
+            a3_p: p(asks, 2),
+            a4_p: p(asks, 3),
+            a5_p: p(asks, 4),
+            b1_v: v(bids, 0),
+            b2_v: v(bids, 1),
+            b3_v: v(bids, 2),
+            b4_v: v(bids, 3),
+            b5_v: v(bids, 4),
+            b1_p: p(bids, 0),
+            b2_p: p(bids, 1),
+            b3_p: p(bids, 2),
+            b4_p: p(bids, 3),
+            b5_p: p(bids, 4),
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_book_measures() {
+        let book = OrderBook::from(&tick(
+            "20200102093003.0",
+            10.01,
+            1000.0,
+            &[(10.0, 300.0), (9.99, 100.0)],
+            &[(10.02, 100.0), (10.03, 400.0)],
+        ));
+        assert_eq!(2, book.iter_levels().count());
+        assert!((book.spread().unwrap() - 0.02).abs() < 1e-9);
+        assert!((book.mid().unwrap() - 10.01).abs() < 1e-9);
+        // more bids push the micro price towards the ask
+        assert!((book.microprice().unwrap() - 10.015).abs() < 1e-9);
+        assert!((book.imbalance(1).unwrap() - 0.5).abs() < 1e-9);
+        // bids 300 + 50, asks 100 + 200
+        assert!((book.imbalance(5).unwrap() - 50.0 / 650.0).abs() < 1e-9);
+        let empty = OrderBook::from(&tick("20200102092500.0", 0.0, 0.0, &[], &[]));
+        assert_eq!(None, empty.spread());
+        assert_eq!(None, empty.imbalance(5));
+    }
+
+    #[test]
+    fn test_book_diff() {
+        let prev = OrderBook::from(&tick(
+            "20200102093003.0",
+            10.01,
+            1000.0,
+            &[(10.0, 300.0), (9.99, 100.0)],
+            &[(10.02, 100.0), (10.03, 400.0), (10.04, 100.0)],
+        ));
+        // 150 bought through 10.02 into 10.03, 50 bids cancelled,
+        // 200 asks added at 10.05 beyond the previous depth
+        let next = OrderBook::from(&tick(
+            "20200102093006.0",
+            10.03,
+            1150.0,
+            &[(10.0, 250.0), (9.99, 100.0)],
+            &[(10.03, 350.0), (10.04, 100.0), (10.05, 200.0)],
+        ));
+        let diff = next.diff(&prev);
+        assert_eq!(150.0, diff.volume);
+        assert_eq!(Some(Side::Ask), diff.aggressor);
+        assert_eq!(
+            vec![
+                LevelChange { side: Side::Ask, price: 10.02, volume: 100.0 },
+                LevelChange { side: Side::Ask, price: 10.03, volume: 50.0 }
+            ],
+            diff.trades
+        );
+        assert_eq!(vec![LevelChange { side: Side::Bid, price: 10.0, volume: 50.0 }], diff.cancels);
+        assert!(diff.adds.is_empty());
+    }
+}
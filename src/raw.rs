@@ -0,0 +1,92 @@
+//! Raw requests
+//!
+//! Executes any method with untyped json parameters, returning
+//! `Table` for csv responses and json value for json responses.
+use crate::{Error, HasMethod, JqdataClient, Result, Table};
+use serde_derive::Serialize;
+use serde_json::{Map, Value};
+
+/// request of arbitrary method, params are flattened
+/// into the request body
+#[derive(Debug, Serialize)]
+pub(crate) struct RawCommand {
+    #[serde(skip)]
+    method: String,
+    #[serde(flatten)]
+    params: Map<String, Value>,
+}
+
+impl HasMethod for RawCommand {
+    fn method(&self) -> String {
+        self.method.to_owned()
+    }
+}
+
+impl RawCommand {
+    pub(crate) fn new(method: &str, params: Value) -> Result<Self> {
+        let params = match params {
+            Value::Null => Map::new(),
+            Value::Object(map) => map,
+            other => return Err(Error::Client(format!("params must be json object, got {}", other))),
+        };
+        Ok(RawCommand {
+            method: method.to_owned(),
+            params,
+        })
+    }
+}
+
+impl JqdataClient {
+    /// Execute method with json object params and return
+    /// the csv response as table
+    pub async fn execute_raw(&self, method: &str, params: Value) -> Result<Table> {
+        let body = self.send_command(RawCommand::new(method, params)?).await?;
+        Table::from_csv(body.as_slice())
+    }
+
+    /// Execute method with json object params and return
+    /// the json response
+    pub async fn execute_raw_json(&self, method: &str, params: Value) -> Result<Value> {
+        let body = self.send_command(RawCommand::new(method, params)?).await?;
+        if body.starts_with(b"error") {
+            return Err(Error::Server(String::from_utf8(body)?));
+        }
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FakeResponse, FakeTransport};
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[test]
+    fn test_execute_raw() {
+        block_on(async {
+            let fake = FakeTransport::new();
+            fake.respond(
+                "get_billboard_list",
+                json!({"code": "000001.XSHE"}),
+                FakeResponse::csv(&["code", "day", "total_value"], &[&["000001.XSHE", "2020-01-02", "1.5e8"]]),
+            );
+            fake.respond("get_fund_info", json!({}), FakeResponse::json(&json!({"fund_name": "test"})).unwrap());
+            let client = JqdataClient::with_transport(fake.clone(), "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            let table = client
+                .execute_raw("get_billboard_list", json!({"code": "000001.XSHE", "date": "2020-01-02"}))
+                .await
+                .unwrap();
+            assert_eq!(vec!["code", "day", "total_value"], table.columns);
+            assert_eq!(vec![Some(1.5e8)], table.parse_column::<f64>("total_value").unwrap());
+            let request = &fake.requests_of("get_billboard_list")[0];
+            assert_eq!(Some(&json!("2020-01-02")), request.params.get("date"));
+
+            let info = client.execute_raw_json("get_fund_info", Value::Null).await.unwrap();
+            assert_eq!(json!("test"), info["fund_name"]);
+            assert!(client.execute_raw("get_price", json!([1])).await.is_err());
+        });
+    }
+}
@@ -0,0 +1,430 @@
+//! Store
+//!
+//! Local storage of bars with incremental synchronization.
+//!
+//! Bars are stored as one csv file per security and unit at
+//! `<root>/<unit>/<code>.csv`, the last synced trade day of each
+//! security is kept in `<root>/<unit>/sync.json`. `sync` only
+//! requests the trade days after it, split into chunks of at most
+//! 1000 trade days as required by get_price_period.
+//!
+//! Csv is used instead of a columnar format because syncing only
+//! appends rows: a csv file is extended in place and stays
+//! readable by any tool, while a parquet file would be rewritten
+//! on every sync. New rows are checked against the last stored
+//! bar, read from the end of the file. Stored bars can be exported
+//! with `rows_to_record_batch` and `write_parquet_file` of feature
+//! "arrow".
+//!
+//! Intraday units are requested until the end of the day sessions
+//! of the last trade day of a chunk. The next chunk starts there,
+//! so night session bars of the evening are fetched with the trade
+//! day they belong to. The first sync of a security starts after
+//! the day sessions of the trade day before the start date.
+use crate::calendar::beijing_now;
+use crate::{GetPricePeriod, GetTradeDays, JqdataClient, Price, Result};
+use chrono::{Duration, NaiveDate, Timelike};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// max trade days of one get_price_period request
+pub const MAX_TRADE_DAYS_PER_REQUEST: usize = 1000;
+
+const SYNC_FILE: &str = "sync.json";
+const DEFAULT_START_DATE: &str = "2005-01-01";
+/// calendar days before the start date searched for the previous
+/// trade day, longer than any exchange holiday
+const PREVIOUS_DAY_SEARCH: i64 = 20;
+/// end of the latest day session, treasury futures close at 15:15
+const DAY_SESSIONS_END: &str = "15:15:00";
+/// bytes read from the end of a data file to find the last row
+const TAIL_BYTES: u64 = 4096;
+
+/// summary of one synchronization
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SyncReport {
+    pub requests: usize,
+    pub rows: usize,
+}
+
+/// Store
+///
+/// local bar storage backed by csv files
+#[derive(Clone)]
+pub struct Store {
+    client: JqdataClient,
+    root: PathBuf,
+    start_date: String,
+}
+
+impl Store {
+    /// open store in directory, created if not exists
+    pub fn open<P: Into<PathBuf>>(client: JqdataClient, root: P) -> Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+        Ok(Store {
+            client,
+            root,
+            start_date: DEFAULT_START_DATE.to_owned(),
+        })
+    }
+
+    /// first date to sync for securities not in store yet
+    pub fn start_date<S: Into<String>>(mut self, start_date: S) -> Self {
+        self.start_date = start_date.into();
+        self
+    }
+
+    fn unit_dir(&self, unit: &str) -> PathBuf {
+        self.root.join(unit)
+    }
+
+    fn data_path(&self, code: &str, unit: &str) -> PathBuf {
+        self.unit_dir(unit).join(format!("{}.csv", code))
+    }
+
+    fn sync_state(&self, unit: &str) -> Result<BTreeMap<String, String>> {
+        let path = self.unit_dir(unit).join(SYNC_FILE);
+        if !path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    fn save_sync_state(&self, unit: &str, state: &BTreeMap<String, String>) -> Result<()> {
+        let path = self.unit_dir(unit).join(SYNC_FILE);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(state)?)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// last synced trade day of security
+    pub fn last_synced(&self, code: &str, unit: &str) -> Result<Option<String>> {
+        Ok(self.sync_state(unit)?.remove(code))
+    }
+
+    /// sync bars of given securities until the last completed trade day
+    pub async fn sync<S: AsRef<str>>(&self, codes: &[S], unit: &str) -> Result<SyncReport> {
+        self.sync_until(codes, unit, &last_complete_day().format("%Y-%m-%d").to_string())
+            .await
+    }
+
+    /// sync bars of given securities until end date
+    pub async fn sync_until<S: AsRef<str>>(
+        &self,
+        codes: &[S],
+        unit: &str,
+        end_date: &str,
+    ) -> Result<SyncReport> {
+        std::fs::create_dir_all(self.unit_dir(unit))?;
+        let mut state = self.sync_state(unit)?;
+        let mut report = SyncReport::default();
+        for code in codes {
+            let code = code.as_ref();
+            let last_synced = state.get(code).cloned();
+            let start_date = match &last_synced {
+                Some(last) => shift_day(last, 1)?,
+                None => self.start_date.clone(),
+            };
+            if start_date.as_str() > end_date {
+                continue;
+            }
+            // the first intraday sync also needs the trade day before start
+            let query_date = match last_synced {
+                None if is_intraday(unit) => shift_day(&start_date, -PREVIOUS_DAY_SEARCH)?,
+                _ => start_date.clone(),
+            };
+            let mut trade_days = self
+                .client
+                .execute(GetTradeDays {
+                    date: query_date,
+                    end_date: Some(end_date.to_owned()),
+                })
+                .await?;
+            report.requests += 1;
+            let split = trade_days.partition_point(|d| d.as_str() < start_date.as_str());
+            let previous = last_synced.or_else(|| split.checked_sub(1).map(|i| trade_days[i].clone()));
+            trade_days.drain(..split);
+            let ranges = request_ranges(unit, previous.as_deref(), &trade_days, MAX_TRADE_DAYS_PER_REQUEST);
+            let mut last_date = last_stored_date(&self.data_path(code, unit))?;
+            for (date, end, last_day) in ranges {
+                let prices = self
+                    .client
+                    .execute(GetPricePeriod {
+                        code: code.to_owned(),
+                        unit: unit.to_owned(),
+                        date,
+                        end_date: end,
+                        fq_ref_date: None,
+                    })
+                    .await?;
+                report.requests += 1;
+                report.rows += self.append(code, unit, &prices, &mut last_date)?;
+                // persist progress after each chunk
+                state.insert(code.to_owned(), last_day);
+                self.save_sync_state(unit, &state)?;
+            }
+        }
+        Ok(report)
+    }
+
+    /// append bars later than last date, the date of the last stored
+    /// bar, which is updated; returns number of rows written
+    fn append(&self, code: &str, unit: &str, prices: &[Price], last_date: &mut Option<String>) -> Result<usize> {
+        let path = self.data_path(code, unit);
+        let is_new = !path.exists() || std::fs::metadata(&path)?.len() == 0;
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(is_new)
+            .from_writer(file);
+        let mut rows = 0;
+        for p in prices {
+            if last_date.as_ref().map(|d| p.date <= *d).unwrap_or(false) {
+                continue;
+            }
+            writer.serialize(p)?;
+            rows += 1;
+            *last_date = Some(p.date.clone());
+        }
+        writer.flush()?;
+        Ok(rows)
+    }
+
+    /// all stored bars of security
+    pub fn read(&self, code: &str, unit: &str) -> Result<Vec<Price>> {
+        read_prices(&self.data_path(code, unit))
+    }
+
+    /// stored bars of security between start and end date, inclusive
+    pub fn read_range(&self, code: &str, unit: &str, start_date: &str, end_date: &str) -> Result<Vec<Price>> {
+        Ok(self
+            .read(code, unit)?
+            .into_iter()
+            .filter(|p| {
+                let day = p.date.get(..10).unwrap_or(&p.date);
+                day >= start_date && day <= end_date
+            })
+            .collect())
+    }
+}
+
+fn read_prices(path: &Path) -> Result<Vec<Price>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut reader = csv::Reader::from_path(path)?;
+    let mut prices = Vec::new();
+    for r in reader.deserialize() {
+        prices.push(r?);
+    }
+    Ok(prices)
+}
+
+/// date of the last row of data file, None if it has no rows
+fn last_stored_date(path: &Path) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL_BYTES)))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let tail = String::from_utf8_lossy(&tail);
+    let last = match tail.lines().rev().find(|l| !l.trim().is_empty()) {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    let date = last.split(',').next().unwrap_or_default();
+    // the header is the only line
+    if date == "date" {
+        return Ok(None);
+    }
+    Ok(Some(date.to_owned()))
+}
+
+fn shift_day(date: &str, days: i64) -> Result<String> {
+    let d = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|e| crate::Error::Client(format!("invalid sync date {}: {}", date, e)))?;
+    Ok((d + Duration::days(days)).format("%Y-%m-%d").to_string())
+}
+
+/// minute units, "1M" is a month
+fn is_intraday(unit: &str) -> bool {
+    unit.ends_with('m')
+}
+
+/// (date, end_date, last trade day) of get_price_period requests
+/// covering trade days in chunks of size, previous is the trade
+/// day before the first one
+fn request_ranges(unit: &str, previous: Option<&str>, trade_days: &[String], size: usize) -> Vec<(String, String, String)> {
+    let intraday = is_intraday(unit);
+    let mut previous = previous.map(str::to_owned);
+    let mut ranges = Vec::new();
+    for chunk in trade_days.chunks(size) {
+        let last_day = chunk[chunk.len() - 1].clone();
+        let range = if intraday {
+            let date = match previous {
+                Some(day) => format!("{} {}", day, DAY_SESSIONS_END),
+                None => chunk[0].clone(),
+            };
+            (date, format!("{} {}", last_day, DAY_SESSIONS_END), last_day.clone())
+        } else {
+            (chunk[0].clone(), last_day.clone(), last_day.clone())
+        };
+        ranges.push(range);
+        previous = Some(last_day);
+    }
+    ranges
+}
+
+/// bars of today are complete after 16:00 Beijing time
+fn last_complete_day() -> NaiveDate {
+    let now = beijing_now();
+    if now.hour() >= 16 {
+        now.date()
+    } else {
+        now.date() - Duration::days(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FakeResponse, FakeTransport};
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    const HEADER: &[&str] = &["date", "open", "close", "high", "low", "volume", "money"];
+
+    #[test]
+    fn test_minute_request_ranges() {
+        let days: Vec<String> = ["2020-01-02", "2020-01-03", "2020-01-06"].iter().map(|d| d.to_string()).collect();
+        let range = |date: &str, end: &str, last: &str| (date.to_owned(), end.to_owned(), last.to_owned());
+        assert_eq!(
+            vec![
+                range("2020-01-02", "2020-01-03 15:15:00", "2020-01-03"),
+                range("2020-01-03 15:15:00", "2020-01-06 15:15:00", "2020-01-06"),
+            ],
+            request_ranges("1m", None, &days, 2)
+        );
+        // incremental sync continues after the day sessions of the last synced day
+        assert_eq!(
+            vec![range("2019-12-31 15:15:00", "2020-01-06 15:15:00", "2020-01-06")],
+            request_ranges("5m", Some("2019-12-31"), &days, 1000)
+        );
+        assert_eq!(
+            vec![range("2020-01-02", "2020-01-03", "2020-01-03"), range("2020-01-06", "2020-01-06", "2020-01-06")],
+            request_ranges("1d", Some("2019-12-31"), &days, 2)
+        );
+    }
+
+    #[test]
+    fn test_incremental_sync() {
+        let dir = tempfile::tempdir().unwrap();
+        block_on(async {
+            let fake = FakeTransport::new();
+            fake.respond(
+                "get_trade_days",
+                json!({"date": "2020-01-01"}),
+                FakeResponse::lines(&["2020-01-02", "2020-01-03"]),
+            )
+            .respond(
+                "get_trade_days",
+                json!({"date": "2020-01-04"}),
+                FakeResponse::lines(&["2020-01-06"]),
+            )
+            .respond(
+                "get_price_period",
+                json!({"date": "2020-01-02", "end_date": "2020-01-03"}),
+                FakeResponse::csv(
+                    HEADER,
+                    &[
+                        &["2020-01-02", "10", "11", "11", "10", "100", "1000"],
+                        &["2020-01-03", "11", "12", "12", "11", "100", "1100"],
+                    ],
+                ),
+            )
+            .respond(
+                "get_price_period",
+                json!({"date": "2020-01-06", "end_date": "2020-01-06"}),
+                FakeResponse::csv(HEADER, &[&["2020-01-06", "12", "13", "13", "12", "100", "1200"]]),
+            );
+            let client = JqdataClient::with_transport(fake.clone(), "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            let store = Store::open(client, dir.path()).unwrap().start_date("2020-01-01");
+            let report = store.sync_until(&["000001.XSHE"], "1d", "2020-01-03").await.unwrap();
+            assert_eq!(SyncReport { requests: 2, rows: 2 }, report);
+            let report = store.sync_until(&["000001.XSHE"], "1d", "2020-01-03").await.unwrap();
+            assert_eq!(SyncReport::default(), report);
+            let report = store.sync_until(&["000001.XSHE"], "1d", "2020-01-06").await.unwrap();
+            assert_eq!(SyncReport { requests: 2, rows: 1 }, report);
+
+            let prices = store.read("000001.XSHE", "1d").unwrap();
+            assert_eq!(
+                vec!["2020-01-02", "2020-01-03", "2020-01-06"],
+                prices.iter().map(|p| p.date.as_str()).collect::<Vec<_>>()
+            );
+            assert_eq!(1, store.read_range("000001.XSHE", "1d", "2020-01-03", "2020-01-05").unwrap().len());
+            assert_eq!(Some("2020-01-06".to_owned()), store.last_synced("000001.XSHE", "1d").unwrap());
+        });
+    }
+
+    #[test]
+    fn test_intraday_sync_over_weekend() {
+        let dir = tempfile::tempdir().unwrap();
+        block_on(async {
+            let fake = FakeTransport::new();
+            // the first sync looks up the trade day before the start date
+            fake.respond(
+                "get_trade_days",
+                json!({"date": "2019-12-13"}),
+                FakeResponse::lines(&["2019-12-30", "2019-12-31", "2020-01-02", "2020-01-03"]),
+            )
+            .respond("get_trade_days", json!({"date": "2020-01-04", "end_date": "2020-01-05"}), FakeResponse::lines(&[]))
+            .respond("get_trade_days", json!({"date": "2020-01-04"}), FakeResponse::lines(&["2020-01-06"]))
+            .respond(
+                "get_price_period",
+                json!({"date": "2019-12-31 15:15:00", "end_date": "2020-01-03 15:15:00"}),
+                FakeResponse::csv(
+                    HEADER,
+                    &[
+                        &["2019-12-31 21:01:00", "10", "11", "11", "10", "100", "1000"],
+                        &["2020-01-03 15:00:00", "11", "12", "12", "11", "100", "1100"],
+                    ],
+                ),
+            )
+            .respond(
+                "get_price_period",
+                json!({"date": "2020-01-03 15:15:00", "end_date": "2020-01-06 15:15:00"}),
+                FakeResponse::csv(
+                    HEADER,
+                    &[
+                        &["2020-01-03 15:00:00", "11", "12", "12", "11", "100", "1100"],
+                        &["2020-01-03 21:01:00", "12", "13", "13", "12", "100", "1200"],
+                    ],
+                ),
+            );
+            let client = JqdataClient::with_transport(fake.clone(), "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            let store = Store::open(client, dir.path()).unwrap().start_date("2020-01-02");
+            let report = store.sync_until(&["AG2006.XSGE"], "1m", "2020-01-03").await.unwrap();
+            assert_eq!(SyncReport { requests: 2, rows: 2 }, report);
+            // a weekend sync keeps the last trade day
+            let report = store.sync_until(&["AG2006.XSGE"], "1m", "2020-01-05").await.unwrap();
+            assert_eq!(SyncReport { requests: 1, rows: 0 }, report);
+            assert_eq!(Some("2020-01-03".to_owned()), store.last_synced("AG2006.XSGE", "1m").unwrap());
+            // friday night bars belong to monday
+            let report = store.sync_until(&["AG2006.XSGE"], "1m", "2020-01-06").await.unwrap();
+            assert_eq!(SyncReport { requests: 2, rows: 1 }, report);
+            assert_eq!(3, store.read("AG2006.XSGE", "1m").unwrap().len());
+            let path = store.data_path("AG2006.XSGE", "1m");
+            assert_eq!(Some("2020-01-03 21:01:00".to_owned()), last_stored_date(&path).unwrap());
+        });
+    }
+}
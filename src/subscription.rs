@@ -0,0 +1,410 @@
+//! Subscription
+//!
+//! Polls get_current_ticks as an async stream of new ticks, there
+//! is no push API for market data.
+//!
+//! Codes are grouped by security type, since one request may only
+//! contain codes of the same type, and each group is split into
+//! batches. Every round requests all batches one after another and
+//! yields ticks whose time changed since the previous round, rounds
+//! start at the given interval. Nothing is polled while the consumer
+//! does not poll the stream, so a slow consumer delays the next
+//! round instead of buffering ticks. A failed request is yielded as
+//! an error and only its batch is skipped by the following rounds
+//! during a backoff doubling up to a maximum, the other batches are
+//! still polled. Ticks without code are skipped in batches of
+//! several codes, as they cannot be told apart.
+use crate::{GetCurrentTicks, JqdataClient, Result, Tick};
+use futures::stream::{self, Stream};
+use futures_timer::Delay;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+const FUTURES_EXCHANGES: &[&str] = &["CCFX", "XSGE", "XDCE", "XZCE", "XINE", "GFEX"];
+
+/// type of security, determines which codes may share a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CodeGroup {
+    Stock,
+    Index,
+    Fund,
+    Futures,
+    Options,
+}
+
+/// guess type of security from its code
+///
+/// futures and options are told apart by the exchange and the
+/// strike in the symbol, stocks, indexes and funds of Shanghai and
+/// Shenzhen by the number ranges, unknown codes count as stocks
+pub fn code_group(code: &str) -> CodeGroup {
+    let (symbol, exchange) = code.split_once('.').unwrap_or((code, ""));
+    if FUTURES_EXCHANGES.contains(&exchange) {
+        // options have letters after the delivery month, e.g. IO2002-C-4000
+        let has_strike = symbol
+            .find(|c: char| c.is_ascii_digit())
+            .map(|i| symbol[i..].chars().any(|c| c.is_ascii_alphabetic()))
+            .unwrap_or(false);
+        return if has_strike { CodeGroup::Options } else { CodeGroup::Futures };
+    }
+    if symbol.len() == 8 {
+        return CodeGroup::Options;
+    }
+    let starts = |prefixes: &[&str]| prefixes.iter().any(|p| symbol.starts_with(p));
+    match exchange {
+        "XSHG" if starts(&["000", "880"]) => CodeGroup::Index,
+        "XSHG" if starts(&["5"]) => CodeGroup::Fund,
+        "XSHE" if starts(&["399"]) => CodeGroup::Index,
+        "XSHE" if starts(&["15", "16", "18"]) => CodeGroup::Fund,
+        _ => CodeGroup::Stock,
+    }
+}
+
+/// Subscription
+///
+/// polls every 3 seconds in batches of 50 codes,
+/// backoff of a failing batch is limited to one minute by default
+#[derive(Clone)]
+pub struct Subscription {
+    client: JqdataClient,
+    codes: Vec<String>,
+    interval: Duration,
+    batch_size: usize,
+    max_backoff: Duration,
+}
+
+impl Subscription {
+    pub fn new<S: Into<String>>(client: JqdataClient, codes: Vec<S>) -> Self {
+        Subscription {
+            client,
+            codes: codes.into_iter().map(Into::into).collect(),
+            interval: Duration::from_secs(3),
+            batch_size: 50,
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+
+    /// time between the starts of two rounds
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// codes per request
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// comma separated codes of each request, groups in order
+    /// of their first code
+    pub fn batches(&self) -> Vec<String> {
+        let mut groups: Vec<(CodeGroup, Vec<&str>)> = Vec::new();
+        for code in &self.codes {
+            let group = code_group(code);
+            match groups.iter_mut().find(|(g, _)| *g == group) {
+                Some((_, codes)) => codes.push(code),
+                None => groups.push((group, vec![code])),
+            }
+        }
+        groups
+            .iter()
+            .flat_map(|(_, codes)| codes.chunks(self.batch_size).map(|c| c.join(",")))
+            .collect()
+    }
+
+    /// endless stream of new ticks
+    pub fn stream(self) -> impl Stream<Item = Result<Tick>> {
+        let state = PollState::new(self);
+        stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(item) = state.buffer.pop_front() {
+                    return Some((item, state));
+                }
+                let wait = state.next_poll.saturating_duration_since(Instant::now());
+                if wait > Duration::from_secs(0) {
+                    Delay::new(wait).await;
+                }
+                let start = Instant::now();
+                state.poll().await;
+                state.next_poll = start + state.subscription.interval;
+            }
+        })
+    }
+}
+
+impl JqdataClient {
+    /// stream of new ticks of codes, polled at interval,
+    /// see `Subscription` for more options
+    pub fn subscribe<S: Into<String>>(&self, codes: Vec<S>, interval: Duration) -> impl Stream<Item = Result<Tick>> {
+        Subscription::new(self.clone(), codes).interval(interval).stream()
+    }
+}
+
+/// codes of one request and its backoff
+struct Batch {
+    codes: String,
+    failures: u32,
+    retry_at: Instant,
+}
+
+struct PollState {
+    subscription: Subscription,
+    batches: Vec<Batch>,
+    /// time of last tick by code
+    last: HashMap<String, String>,
+    buffer: VecDeque<Result<Tick>>,
+    next_poll: Instant,
+}
+
+impl PollState {
+    fn new(subscription: Subscription) -> Self {
+        let now = Instant::now();
+        PollState {
+            batches: subscription
+                .batches()
+                .into_iter()
+                .map(|codes| Batch {
+                    codes,
+                    failures: 0,
+                    retry_at: now,
+                })
+                .collect(),
+            subscription,
+            last: HashMap::new(),
+            buffer: VecDeque::new(),
+            next_poll: now,
+        }
+    }
+
+    fn backoff(&self, failures: u32) -> Duration {
+        let factor = 1u32 << failures.min(16);
+        (self.subscription.interval * factor).min(self.subscription.max_backoff)
+    }
+
+    /// one round over all batches not in backoff, new ticks and
+    /// errors are buffered in batch order
+    async fn poll(&mut self) {
+        for idx in 0..self.batches.len() {
+            if self.batches[idx].retry_at > Instant::now() {
+                continue;
+            }
+            let batch = self.batches[idx].codes.clone();
+            let ticks = match self
+                .subscription
+                .client
+                .execute(GetCurrentTicks { code: batch.clone() })
+                .await
+            {
+                Ok(ticks) => {
+                    self.batches[idx].failures = 0;
+                    ticks
+                }
+                Err(e) => {
+                    let failures = self.batches[idx].failures + 1;
+                    self.batches[idx].failures = failures;
+                    self.batches[idx].retry_at = Instant::now() + self.backoff(failures);
+                    self.buffer.push_back(Err(e));
+                    continue;
+                }
+            };
+            for mut tick in ticks {
+                if tick.code.is_none() && !batch.contains(',') {
+                    tick.code = Some(batch.clone());
+                }
+                let code = match tick.code.clone() {
+                    Some(code) => code,
+                    None => continue,
+                };
+                if self.last.get(&code) == Some(&tick.time) {
+                    continue;
+                }
+                self.last.insert(code, tick.time.clone());
+                self.buffer.push_back(Ok(tick));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FakeResponse, FakeTransport};
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use serde_json::json;
+
+    #[test]
+    fn test_code_group() {
+        assert_eq!(CodeGroup::Stock, code_group("600000.XSHG"));
+        assert_eq!(CodeGroup::Stock, code_group("300750.XSHE"));
+        assert_eq!(CodeGroup::Index, code_group("000300.XSHG"));
+        assert_eq!(CodeGroup::Index, code_group("399006.XSHE"));
+        assert_eq!(CodeGroup::Fund, code_group("510300.XSHG"));
+        assert_eq!(CodeGroup::Fund, code_group("159915.XSHE"));
+        assert_eq!(CodeGroup::Futures, code_group("AG2006.XSGE"));
+        assert_eq!(CodeGroup::Options, code_group("IO2002-C-4000.CCFX"));
+        assert_eq!(CodeGroup::Options, code_group("CU2005C40000.XSGE"));
+        assert_eq!(CodeGroup::Options, code_group("10002713.XSHG"));
+    }
+
+    #[test]
+    fn test_batches() {
+        let codes = vec!["000001.XSHE", "000300.XSHG", "600000.XSHG", "300750.XSHE", "399006.XSHE"];
+        let client = block_on(JqdataClient::with_transport(FakeTransport::new(), "10000".to_owned(), "pass".to_owned())).unwrap();
+        let sub = Subscription::new(client, codes).batch_size(2);
+        assert_eq!(
+            vec!["000001.XSHE,600000.XSHG", "300750.XSHE", "000300.XSHG,399006.XSHE"],
+            sub.batches()
+        );
+    }
+
+    #[test]
+    fn test_stream_new_ticks() {
+        const HEADER: &[&str] = &["code", "time", "current", "high", "low", "volume", "money"];
+        let stocks = json!({"code": "000001.XSHE,600000.XSHG"});
+        let fake = FakeTransport::new();
+        fake.respond_once(
+            "get_current_ticks",
+            stocks.clone(),
+            FakeResponse::csv(
+                HEADER,
+                &[
+                    &["000001.XSHE", "20200102093003.0", "16.6", "16.6", "16.5", "100", "1660"],
+                    &["600000.XSHG", "20200102093003.0", "12.5", "12.5", "12.5", "100", "1250"],
+                ],
+            ),
+        )
+        .respond_once(
+            "get_current_ticks",
+            stocks.clone(),
+            FakeResponse::csv(
+                HEADER,
+                &[
+                    &["000001.XSHE", "20200102093003.0", "16.6", "16.6", "16.5", "100", "1660"],
+                    &["600000.XSHG", "20200102093006.0", "12.6", "12.6", "12.5", "200", "2510"],
+                ],
+            ),
+        )
+        .respond_once("get_current_ticks", stocks.clone(), FakeResponse::error("connection reset"))
+        .respond(
+            "get_current_ticks",
+            stocks,
+            FakeResponse::csv(
+                HEADER,
+                &[
+                    &["000001.XSHE", "20200102093009.0", "16.7", "16.7", "16.5", "300", "4990"],
+                    &["600000.XSHG", "20200102093006.0", "12.6", "12.6", "12.5", "200", "2510"],
+                ],
+            ),
+        )
+        .respond(
+            "get_current_ticks",
+            json!({"code": "000300.XSHG"}),
+            FakeResponse::csv(&HEADER[1..], &[&["20200102093000.0", "4100", "4100", "4100", "1000", "4.1e6"]]),
+        );
+        let items: Vec<Result<Tick>> = block_on(async {
+            let client = JqdataClient::with_transport(fake.clone(), "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            Subscription::new(client, vec!["000001.XSHE", "600000.XSHG", "000300.XSHG"])
+                .interval(Duration::from_millis(1))
+                .max_backoff(Duration::from_millis(5))
+                .stream()
+                .take(6)
+                .collect()
+                .await
+        });
+        let seen: Vec<Option<(String, String)>> = items
+            .iter()
+            .map(|r| r.as_ref().ok().map(|t| (t.code.clone().unwrap(), t.time[8..14].to_owned())))
+            .collect();
+        let tick = |code: &str, time: &str| Some((code.to_owned(), time.to_owned()));
+        assert_eq!(
+            vec![
+                tick("000001.XSHE", "093003"),
+                tick("600000.XSHG", "093003"),
+                tick("000300.XSHG", "093000"),
+                tick("600000.XSHG", "093006"),
+                None,
+                tick("000001.XSHE", "093009"),
+            ],
+            seen
+        );
+    }
+
+    #[test]
+    fn test_skip_ticks_without_code_in_batch() {
+        const HEADER: &[&str] = &["time", "current", "high", "low", "volume", "money"];
+        let fake = FakeTransport::new();
+        fake.respond(
+            "get_current_ticks",
+            json!({}),
+            FakeResponse::csv(
+                HEADER,
+                &[
+                    &["20200102093003.0", "16.6", "16.6", "16.5", "100", "1660"],
+                    &["20200102093006.0", "12.5", "12.5", "12.5", "100", "1250"],
+                ],
+            ),
+        );
+        let state = block_on(async {
+            let client = JqdataClient::with_transport(fake.clone(), "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            let subscription = Subscription::new(client, vec!["000001.XSHE", "600000.XSHG", "000300.XSHG"]);
+            let mut state = PollState::new(subscription);
+            state.poll().await;
+            state
+        });
+        // only the tick of the single code batch is kept
+        let codes: Vec<Option<String>> = state
+            .buffer
+            .iter()
+            .map(|t| t.as_ref().unwrap().code.clone())
+            .collect();
+        assert_eq!(vec![Some("000300.XSHG".to_owned()), Some("000300.XSHG".to_owned())], codes);
+        assert!(!state.last.contains_key(""));
+    }
+
+    #[test]
+    fn test_failing_batch_backs_off_alone() {
+        let fake = FakeTransport::new();
+        fake.respond("get_current_ticks", json!({"code": "000001.XSHE"}), FakeResponse::error("connection reset"))
+            .respond(
+                "get_current_ticks",
+                json!({"code": "000300.XSHG"}),
+                FakeResponse::csv(
+                    &["time", "current", "high", "low", "volume", "money"],
+                    &[&["20200102093000.0", "4100", "4100", "4100", "1000", "4.1e6"]],
+                ),
+            );
+        let state = block_on(async {
+            let client = JqdataClient::with_transport(fake.clone(), "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            let subscription = Subscription::new(client, vec!["000001.XSHE", "000300.XSHG"]);
+            let mut state = PollState::new(subscription);
+            state.poll().await;
+            state.poll().await;
+            state
+        });
+        // the index batch is polled in both rounds despite the
+        // failure before it, the failing batch only in the first
+        let polled: Vec<String> = fake
+            .requests_of("get_current_ticks")
+            .into_iter()
+            .map(|r| r.params["code"].as_str().unwrap().to_owned())
+            .collect();
+        assert_eq!(vec!["000001.XSHE", "000300.XSHG", "000300.XSHG"], polled);
+        assert_eq!(2, state.buffer.len());
+        assert!(state.buffer[0].is_err());
+        assert_eq!(Some("000300.XSHG".to_owned()), state.buffer[1].as_ref().unwrap().code);
+        assert_eq!(1, state.batches[0].failures);
+    }
+}
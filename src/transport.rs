@@ -0,0 +1,194 @@
+//! Transport
+//!
+//! HTTP layer of the client, hidden behind the `Transport` trait
+//! so that the client does not depend on a specific HTTP library
+//! or async runtime.
+//!
+//! Available implementations:
+//! `ReqwestTransport`: enabled by feature "reqwest" (default),
+//! requires a tokio 1.x runtime.
+//! `UreqTransport`: enabled by feature "ureq", runs the blocking
+//! ureq agent on a background thread and works with any executor.
+use crate::Result;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+
+/// JQData API url
+pub const JQDATA_URL: &str = "https://dataapi.joinquant.com/apis";
+
+/// Transport
+///
+/// sends a JSON request body to JQData API and returns
+/// the raw response body
+pub trait Transport: Send + Sync {
+    fn send(&self, body: String) -> BoxFuture<'_, Result<Vec<u8>>>;
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn send(&self, body: String) -> BoxFuture<'_, Result<Vec<u8>>> {
+        (**self).send(body)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn send(&self, body: String) -> BoxFuture<'_, Result<Vec<u8>>> {
+        (**self).send(body)
+    }
+}
+
+/// default transport selected by enabled features,
+/// reqwest is preferred if both are enabled
+#[cfg(feature = "reqwest")]
+pub(crate) fn default_transport() -> Arc<dyn Transport> {
+    Arc::new(ReqwestTransport::new())
+}
+
+#[cfg(all(feature = "ureq", not(feature = "reqwest")))]
+pub(crate) fn default_transport() -> Arc<dyn Transport> {
+    Arc::new(UreqTransport::new())
+}
+
+/// ReqwestTransport
+///
+/// transport based on async reqwest client
+#[cfg(feature = "reqwest")]
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[cfg(feature = "reqwest")]
+impl ReqwestTransport {
+    pub fn new() -> Self {
+        Self::with_url(JQDATA_URL)
+    }
+
+    /// create transport sending requests to given url
+    pub fn with_url<S: Into<String>>(url: S) -> Self {
+        ReqwestTransport {
+            client: reqwest::Client::new(),
+            url: url.into(),
+        }
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl Default for ReqwestTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl Transport for ReqwestTransport {
+    fn send(&self, body: String) -> BoxFuture<'_, Result<Vec<u8>>> {
+        use reqwest::header::{HeaderValue, CONTENT_TYPE};
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(&self.url)
+                .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                .body(body)
+                .send()
+                .await
+                .map_err(|e| crate::Error::Client(e.to_string()))?
+                .bytes()
+                .await
+                .map_err(|e| crate::Error::Client(e.to_string()))?;
+            Ok(response.to_vec())
+        })
+    }
+}
+
+/// UreqTransport
+///
+/// transport based on blocking ureq agent, each request is
+/// performed on a separate thread and the result is sent
+/// back through a oneshot channel
+#[cfg(feature = "ureq")]
+#[derive(Debug, Clone)]
+pub struct UreqTransport {
+    agent: ureq::Agent,
+    url: String,
+}
+
+#[cfg(feature = "ureq")]
+impl UreqTransport {
+    pub fn new() -> Self {
+        Self::with_url(JQDATA_URL)
+    }
+
+    /// create transport sending requests to given url
+    pub fn with_url<S: Into<String>>(url: S) -> Self {
+        UreqTransport {
+            agent: ureq::Agent::new(),
+            url: url.into(),
+        }
+    }
+}
+
+#[cfg(feature = "ureq")]
+impl Default for UreqTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "ureq")]
+impl Transport for UreqTransport {
+    fn send(&self, body: String) -> BoxFuture<'_, Result<Vec<u8>>> {
+        let agent = self.agent.clone();
+        let url = self.url.clone();
+        // the thread is spawned on first poll, so a dropped future
+        // sends no request
+        Box::pin(async move {
+            let (tx, rx) = futures::channel::oneshot::channel();
+            std::thread::spawn(move || {
+                let result = agent
+                    .post(&url)
+                    .set("Content-Type", "application/json")
+                    .send_string(&body)
+                    .map_err(|e| crate::Error::Client(e.to_string()))
+                    .and_then(|response| {
+                        let mut bytes = Vec::new();
+                        std::io::copy(&mut response.into_reader(), &mut bytes)?;
+                        Ok(bytes)
+                    });
+                let _ = tx.send(result);
+            });
+            rx.await
+                .map_err(|_| crate::Error::Client("ureq transport thread terminated".to_owned()))?
+        })
+    }
+}
+
+#[cfg(all(test, feature = "ureq"))]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use mockito::mock;
+
+    #[test]
+    fn test_ureq_transport() {
+        let api = mock("POST", "/ureq")
+            .match_header("content-type", "application/json")
+            .match_body(r#"{"method":"get_query_count"}"#)
+            .with_status(200)
+            .with_body("9999")
+            .create();
+        let transport = UreqTransport::with_url(format!("{}/ureq", mockito::server_url()));
+        let body = block_on(transport.send(r#"{"method":"get_query_count"}"#.to_owned())).unwrap();
+        assert_eq!(b"9999".to_vec(), body);
+        api.assert();
+    }
+
+    #[test]
+    fn test_ureq_dropped_future_sends_nothing() {
+        let api = mock("POST", "/ureq-dropped").expect(0).create();
+        let transport = UreqTransport::with_url(format!("{}/ureq-dropped", mockito::server_url()));
+        drop(transport.send("{}".to_owned()));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        api.assert();
+    }
+}
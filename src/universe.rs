@@ -0,0 +1,423 @@
+//! Universe
+//!
+//! Survivorship-free lists of tradable securities per trade day,
+//! with the usual filters of stock strategies.
+//!
+//! Candidates are the securities returned by get_all_securities
+//! without date, which includes delisted securities, whose listing
+//! overlaps the range. So securities listed or delisted within the
+//! range are included. A security is listed from its start_date
+//! until the day before its end_date. ST flags come from get_extras,
+//! suspension and limit prices from daily bars of get_price_period;
+//! a trade day without bar counts as suspended. Per-security data is
+//! only fetched if a filter needs it.
+use crate::store::MAX_TRADE_DAYS_PER_REQUEST;
+use crate::{
+    Error, GetAllSecurities, GetExtras, GetPricePeriod, GetTradeDays, JqdataClient, Numeric, Result, Security,
+    SecurityKind,
+};
+use chrono::NaiveDate;
+use futures::future::join_all;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+fn day_of(date: &str) -> &str {
+    date.get(..10).unwrap_or(date)
+}
+
+fn parse_date(s: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(day_of(s), "%Y-%m-%d")
+        .map_err(|e| Error::Client(format!("invalid date {}: {}", s, e)))
+}
+
+/// state of a security on a trade day
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyStatus {
+    pub code: String,
+    pub date: String,
+    /// calendar days since listing, 0 on the listing day
+    pub listed_days: i64,
+    pub is_st: bool,
+    pub paused: bool,
+    /// closed at the limit price
+    pub limit_up: bool,
+    pub limit_down: bool,
+}
+
+/// UniverseFilter
+///
+/// conditions excluding a security on a trade day,
+/// nothing is excluded by default
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UniverseFilter {
+    exclude_st: bool,
+    min_listed_days: i64,
+    exclude_paused: bool,
+    exclude_limit_up: bool,
+    exclude_limit_down: bool,
+}
+
+impl UniverseFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn exclude_st(mut self, exclude: bool) -> Self {
+        self.exclude_st = exclude;
+        self
+    }
+
+    /// exclude securities listed for fewer calendar days
+    pub fn min_listed_days(mut self, days: i64) -> Self {
+        self.min_listed_days = days;
+        self
+    }
+
+    pub fn exclude_paused(mut self, exclude: bool) -> Self {
+        self.exclude_paused = exclude;
+        self
+    }
+
+    pub fn exclude_limit_up(mut self, exclude: bool) -> Self {
+        self.exclude_limit_up = exclude;
+        self
+    }
+
+    pub fn exclude_limit_down(mut self, exclude: bool) -> Self {
+        self.exclude_limit_down = exclude;
+        self
+    }
+
+    pub fn accepts(&self, status: &DailyStatus) -> bool {
+        !(self.exclude_st && status.is_st
+            || status.listed_days < self.min_listed_days
+            || self.exclude_paused && status.paused
+            || self.exclude_limit_up && status.limit_up
+            || self.exclude_limit_down && status.limit_down)
+    }
+
+    fn needs_prices(&self) -> bool {
+        self.exclude_paused || self.exclude_limit_up || self.exclude_limit_down
+    }
+}
+
+/// Universe
+///
+/// eligible securities of each trade day
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Universe {
+    pub dates: BTreeMap<String, Vec<String>>,
+}
+
+impl Universe {
+    /// eligible securities on trade day, empty on other days
+    pub fn on(&self, date: &str) -> &[String] {
+        self.dates.get(date).map(|c| c.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn contains(&self, code: &str, date: &str) -> bool {
+        self.on(date).iter().any(|c| c == code)
+    }
+}
+
+/// daily data of one security by date
+#[derive(Debug, Default)]
+struct SecurityDays {
+    st: HashSet<String>,
+    /// (paused, limit up, limit down) of days with bar
+    bars: HashMap<String, (bool, bool, bool)>,
+}
+
+/// UniverseBuilder
+///
+/// stocks without filter by default
+#[derive(Clone)]
+pub struct UniverseBuilder {
+    client: JqdataClient,
+    kind: SecurityKind,
+    filter: UniverseFilter,
+    concurrency: usize,
+}
+
+impl UniverseBuilder {
+    pub fn new(client: JqdataClient) -> Self {
+        UniverseBuilder {
+            client,
+            kind: SecurityKind::Stock,
+            filter: UniverseFilter::default(),
+            concurrency: 4,
+        }
+    }
+
+    pub fn kind(mut self, kind: SecurityKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn filter(mut self, filter: UniverseFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// number of requests in flight, 4 by default
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// securities listed on any day between start and end date
+    async fn candidates(&self, start_date: &str, end_date: &str) -> Result<Vec<Security>> {
+        let mut securities = self
+            .client
+            .execute(GetAllSecurities {
+                code: self.kind,
+                date: None,
+            })
+            .await?;
+        securities.retain(|s| day_of(&s.start_date) <= end_date && day_of(&s.end_date) > start_date);
+        securities.sort_by(|a, b| a.code.cmp(&b.code));
+        Ok(securities)
+    }
+
+    async fn security_days(&self, code: &str, days: &[String]) -> Result<SecurityDays> {
+        let mut data = SecurityDays::default();
+        for chunk in days.chunks(MAX_TRADE_DAYS_PER_REQUEST) {
+            let (first, last) = (chunk[0].clone(), chunk[chunk.len() - 1].clone());
+            if self.filter.exclude_st {
+                let extras = self
+                    .client
+                    .execute(GetExtras {
+                        code: code.to_owned(),
+                        date: first.clone(),
+                        end_date: last.clone(),
+                    })
+                    .await?;
+                data.st
+                    .extend(extras.into_iter().filter(|e| e.is_st == Some(1)).map(|e| day_of(&e.date).to_owned()));
+            }
+            if self.filter.needs_prices() {
+                let prices = self
+                    .client
+                    .execute(GetPricePeriod {
+                        code: code.to_owned(),
+                        unit: "1d".to_owned(),
+                        date: first,
+                        end_date: last,
+                        fq_ref_date: None,
+                    })
+                    .await?;
+                for p in prices {
+                    let close = p.close.to_f64();
+                    let at = |limit: &Option<crate::Number>| {
+                        limit.as_ref().map(|l| (close - l.to_f64()).abs() < 1e-6).unwrap_or(false)
+                    };
+                    let status = (p.paused == Some(1), at(&p.high_limit), at(&p.low_limit));
+                    data.bars.insert(day_of(&p.date).to_owned(), status);
+                }
+            }
+        }
+        Ok(data)
+    }
+
+    async fn trade_days(&self, start_date: &str, end_date: &str) -> Result<Vec<String>> {
+        self.client
+            .execute(GetTradeDays {
+                date: start_date.to_owned(),
+                end_date: Some(end_date.to_owned()),
+            })
+            .await
+    }
+
+    /// state of every listed candidate on every trade day
+    pub async fn statuses(&self, start_date: &str, end_date: &str) -> Result<Vec<DailyStatus>> {
+        let days = self.trade_days(start_date, end_date).await?;
+        self.statuses_of(&days, start_date, end_date).await
+    }
+
+    async fn statuses_of(&self, days: &[String], start_date: &str, end_date: &str) -> Result<Vec<DailyStatus>> {
+        let candidates = self.candidates(start_date, end_date).await?;
+        // listed trade days of each candidate
+        let mut listed: Vec<(&Security, NaiveDate, Vec<String>)> = Vec::new();
+        for s in &candidates {
+            let listing = parse_date(&s.start_date)?;
+            let days: Vec<String> = days
+                .iter()
+                .filter(|d| d.as_str() >= s.start_date.as_str() && d.as_str() < s.end_date.as_str())
+                .cloned()
+                .collect();
+            if !days.is_empty() {
+                listed.push((s, listing, days));
+            }
+        }
+        let needs_data = self.filter.exclude_st || self.filter.needs_prices();
+        let mut statuses = Vec::new();
+        for batch in listed.chunks(self.concurrency) {
+            let data = if needs_data {
+                join_all(batch.iter().map(|(s, _, days)| self.security_days(&s.code, days)))
+                    .await
+                    .into_iter()
+                    .collect::<Result<Vec<_>>>()?
+            } else {
+                batch.iter().map(|_| SecurityDays::default()).collect()
+            };
+            for ((s, listing, days), data) in batch.iter().zip(data) {
+                for day in days {
+                    let (paused, limit_up, limit_down) = if self.filter.needs_prices() {
+                        data.bars.get(day).cloned().unwrap_or((true, false, false))
+                    } else {
+                        (false, false, false)
+                    };
+                    statuses.push(DailyStatus {
+                        code: s.code.clone(),
+                        date: day.clone(),
+                        listed_days: (parse_date(day)? - *listing).num_days(),
+                        is_st: data.st.contains(day),
+                        paused,
+                        limit_up,
+                        limit_down,
+                    });
+                }
+            }
+        }
+        Ok(statuses)
+    }
+
+    /// eligible securities of each trade day between start and end
+    pub async fn build(&self, start_date: &str, end_date: &str) -> Result<Universe> {
+        let days = self.trade_days(start_date, end_date).await?;
+        let mut universe = Universe::default();
+        for day in &days {
+            universe.dates.insert(day.clone(), Vec::new());
+        }
+        for status in self.statuses_of(&days, start_date, end_date).await? {
+            if self.filter.accepts(&status) {
+                universe.dates.entry(status.date).or_default().push(status.code);
+            }
+        }
+        Ok(universe)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FakeResponse, FakeTransport};
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    const SECURITY_HEADER: &[&str] = &["code", "display_name", "name", "start_date", "end_date", "type"];
+    const PRICE_HEADER: &[&str] = &["date", "open", "close", "high", "low", "volume", "money", "paused", "high_limit", "low_limit"];
+
+    fn fake() -> FakeTransport {
+        let fake = FakeTransport::new();
+        fake.respond("get_trade_days", json!({}), FakeResponse::lines(&["2020-01-02", "2020-01-03", "2020-01-06"]))
+            // 000004 is delisted on 2020-01-03, 000005 listed and delisted
+            // within the range, 000006 delisted before and 000007 listed after it
+            .respond(
+                "get_all_securities",
+                json!({}),
+                FakeResponse::csv(
+                    SECURITY_HEADER,
+                    &[
+                        &["000001.XSHE", "平安银行", "PAYH", "1991-04-03", "2200-01-01", "stock"],
+                        &["000002.XSHE", "万科A", "WKA", "2019-12-10", "2200-01-01", "stock"],
+                        &["000003.XSHE", "ST三号", "STSH", "2010-01-04", "2200-01-01", "stock"],
+                        &["000004.XSHE", "国农科技", "GNKJ", "1991-01-14", "2020-01-03", "stock"],
+                        &["000005.XSHE", "五号", "WH", "2020-01-03", "2020-01-06", "stock"],
+                        &["000006.XSHE", "六号", "LH", "2001-01-01", "2019-06-01", "stock"],
+                        &["000007.XSHE", "七号", "QH", "2020-02-03", "2200-01-01", "stock"],
+                    ],
+                ),
+            )
+            .respond(
+                "get_extras",
+                json!({"code": "000003.XSHE"}),
+                FakeResponse::csv(&["date", "is_st"], &[&["2020-01-02", "0"], &["2020-01-03", "1"], &["2020-01-06", "1"]]),
+            )
+            .respond(
+                "get_extras",
+                json!({}),
+                FakeResponse::csv(&["date", "is_st"], &[&["2020-01-02", "0"], &["2020-01-03", "0"], &["2020-01-06", "0"]]),
+            )
+            // 000001 closes at the limit on 2020-01-02 and has no bar on 2020-01-06
+            .respond(
+                "get_price_period",
+                json!({"code": "000001.XSHE"}),
+                FakeResponse::csv(
+                    PRICE_HEADER,
+                    &[
+                        &["2020-01-02", "10", "11", "11", "10", "100", "1000", "0", "11", "9"],
+                        &["2020-01-03", "11", "11.5", "11.6", "11", "100", "1000", "0", "12.1", "9.9"],
+                    ],
+                ),
+            )
+            .respond(
+                "get_price_period",
+                json!({}),
+                FakeResponse::csv(
+                    PRICE_HEADER,
+                    &[
+                        &["2020-01-02", "10", "10", "10", "10", "100", "1000", "0", "11", "9"],
+                        &["2020-01-03", "10", "10", "10", "10", "100", "1000", "0", "11", "9"],
+                        &["2020-01-06", "10", "10", "10", "10", "100", "1000", "0", "11", "9"],
+                    ],
+                ),
+            );
+        fake
+    }
+
+    fn build(fake: FakeTransport, filter: UniverseFilter) -> Universe {
+        block_on(async {
+            let client = JqdataClient::with_transport(fake, "10000".to_owned(), "pass".to_owned())
+                .await
+                .unwrap();
+            UniverseBuilder::new(client)
+                .filter(filter)
+                .build("2020-01-02", "2020-01-06")
+                .await
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_unfiltered_universe() {
+        let fake = fake();
+        let universe = build(fake.clone(), UniverseFilter::new());
+        assert_eq!(
+            &["000001.XSHE", "000002.XSHE", "000003.XSHE", "000004.XSHE"],
+            universe.on("2020-01-02")
+        );
+        assert!(!universe.contains("000004.XSHE", "2020-01-03"));
+        assert!(universe.on("2020-01-04").is_empty());
+        assert!(fake.requests_of("get_price_period").is_empty());
+        assert!(fake.requests_of("get_extras").is_empty());
+    }
+
+    #[test]
+    fn test_listed_and_delisted_within_range() {
+        let fake = fake();
+        let universe = build(fake.clone(), UniverseFilter::new());
+        assert!(!universe.contains("000005.XSHE", "2020-01-02"));
+        assert!(universe.contains("000005.XSHE", "2020-01-03"));
+        assert!(!universe.contains("000005.XSHE", "2020-01-06"));
+        let codes: HashSet<&String> = universe.dates.values().flatten().collect();
+        assert!(!codes.iter().any(|c| *c == "000006.XSHE" || *c == "000007.XSHE"));
+        // one undated request for all candidates
+        let requests = fake.requests_of("get_all_securities");
+        assert_eq!(1, requests.len());
+        assert!(requests[0].params.get("date").is_none());
+    }
+
+    #[test]
+    fn test_filtered_universe() {
+        let filter = UniverseFilter::new()
+            .exclude_st(true)
+            .min_listed_days(30)
+            .exclude_paused(true)
+            .exclude_limit_up(true);
+        let universe = build(fake(), filter);
+        assert_eq!(&["000003.XSHE", "000004.XSHE"], universe.on("2020-01-02"));
+        assert_eq!(&["000001.XSHE"], universe.on("2020-01-03"));
+        // 000002 has been listed for 27 days on 2020-01-06
+        assert!(universe.on("2020-01-06").is_empty());
+    }
+}
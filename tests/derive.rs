@@ -0,0 +1,72 @@
+//! request types defined outside of jqdata-model
+
+use futures::executor::block_on;
+use jqdata::{CsvRow, FakeResponse, FakeTransport, Jqdata, JqdataClient};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+/// endpoint not modeled by jqdata-model, with rows of its own type
+#[derive(Debug, Serialize, Deserialize, Jqdata)]
+#[jqdata(crate = "jqdata")]
+#[method("get_fund_holdings")]
+#[consume(format = "csv", type = "FundHolding")]
+struct GetFundHoldings {
+    code: String,
+    date: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FundHolding {
+    code: String,
+    proportion: f64,
+    #[serde(skip)]
+    extra: BTreeMap<String, String>,
+}
+
+impl CsvRow for FundHolding {
+    fn extra_mut(&mut self) -> Option<&mut BTreeMap<String, String>> {
+        Some(&mut self.extra)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Jqdata)]
+#[jqdata(crate = "jqdata")]
+#[method("get_fund_managers")]
+#[consume(format = "line")]
+struct GetFundManagers {
+    code: String,
+}
+
+#[test]
+fn test_external_request_types() {
+    block_on(async {
+        let fake = FakeTransport::new();
+        fake.respond(
+            "get_fund_holdings",
+            json!({"code": "510300.XSHG"}),
+            FakeResponse::csv(&["code", "proportion", "name"], &[&["600519.XSHG", "5.2", "贵州茅台"]]),
+        )
+        .respond("get_fund_managers", json!({}), FakeResponse::lines(&["a", "b"]));
+        let client = JqdataClient::with_transport(fake, "10000".to_owned(), "pass".to_owned())
+            .await
+            .unwrap();
+        let holdings = client
+            .execute(GetFundHoldings {
+                code: "510300.XSHG".to_owned(),
+                date: "2020-06-30".to_owned(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(5.2, holdings[0].proportion);
+        assert_eq!("600519.XSHG", holdings[0].code);
+        assert_eq!(Some(&"贵州茅台".to_owned()), holdings[0].extra.get("name"));
+        let managers = client
+            .execute(GetFundManagers {
+                code: "510300.XSHG".to_owned(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(vec!["a", "b"], managers);
+    });
+}
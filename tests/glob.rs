@@ -0,0 +1,14 @@
+//! glob import of jqdata next to the futures crate
+
+use jqdata::*;
+
+#[test]
+fn test_glob_import_keeps_futures_crate() {
+    let client = futures::executor::block_on(JqdataClient::with_transport(
+        FakeTransport::new(),
+        "10000".to_owned(),
+        "pass".to_owned(),
+    ))
+    .unwrap();
+    let _ = ContinuousFutures::new(client).adjustment(Adjustment::Ratio);
+}